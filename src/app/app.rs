@@ -1,31 +1,67 @@
+use std::cmp::Reverse;
 use std::collections::HashMap;
+use std::net::IpAddr;
 use std::sync::Arc;
 
+use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
 use crate::app::error::{ProcessError, SendError};
-use crate::app::{Player, Room};
+use crate::app::{Config, Matchmaker, Player, QueueKey, Room};
 use crate::code::Code;
-use crate::proto::s2c;
+use crate::proto::c2s::{HostMigration, RoomFilter, RoomOptions};
+use crate::proto::s2c::{self, MemberRole, RoomCloseReason};
+use crate::proto::{Capabilities, RequestId, ResumeToken};
+
+/// Number of random codes tried for a new room before giving up.
+const MAX_CODE_ATTEMPTS: usize = 16;
+
+/// Share of the code space that may be in use, above which collisions get too frequent to keep
+/// drawing random codes.
+const MAX_CODE_SPACE_USAGE: f64 = 0.5;
 
 pub struct App {
+    config: Config,
     players: HashMap<Uuid, Arc<RwLock<Player>>>,
     rooms: HashMap<Code, Arc<RwLock<Room>>>,
+    resume_tokens: HashMap<ResumeToken, Uuid>,
+    matchmaker: Matchmaker,
+}
+
+impl Default for App {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl App {
     pub fn new() -> Self {
+        Self::with_config(Config::default())
+    }
+
+    pub fn with_config(config: Config) -> Self {
         Self {
+            config,
             players: HashMap::new(),
             rooms: HashMap::new(),
+            resume_tokens: HashMap::new(),
+            matchmaker: Matchmaker::default(),
         }
     }
 
+    pub fn get_config(&self) -> &Config {
+        &self.config
+    }
+
     pub fn get_players(&self) -> impl Iterator<Item = &Arc<RwLock<Player>>> {
         self.players.values()
     }
 
+    pub fn get_player(&self, session_id: &Uuid) -> Option<&Arc<RwLock<Player>>> {
+        self.players.get(session_id)
+    }
+
     pub fn get_rooms(&self) -> impl Iterator<Item = &Arc<RwLock<Room>>> {
         self.rooms.values()
     }
@@ -34,14 +70,26 @@ impl App {
         self.rooms.get(code)
     }
 
+    /// Returns the public rooms matching a filter.
+    pub async fn find_rooms(&self, filter: &RoomFilter<'_>) -> Vec<Arc<RwLock<Room>>> {
+        let mut rooms = vec![];
+
+        for room in self.rooms.values() {
+            if room.read().await.matches(filter) {
+                rooms.push(room.clone());
+            }
+        }
+
+        rooms
+    }
+
     pub async fn add_player(&mut self, player: Player) -> Result<Arc<RwLock<Player>>, SendError> {
         let session_id = *player.get_session_id();
 
-        player
-            .send(&s2c::Message::AssignSessionId {
-                session_id: &session_id,
-            })
-            .await?;
+        player.send_handshake().await?;
+
+        self.resume_tokens
+            .insert(*player.get_resume_token(), session_id);
 
         let player = Arc::new(RwLock::new(player));
 
@@ -52,32 +100,111 @@ impl App {
         Ok(player)
     }
 
+    /// Hands the session matching a resume token over to a new connection.
+    ///
+    /// Returns `None` if the token doesn't match any session.
+    pub async fn resume_player(
+        &mut self,
+        resume_token: &ResumeToken,
+        tx: UnboundedSender<Vec<u8>>,
+        remote_ip: Option<IpAddr>,
+        protocol_version: u16,
+        capabilities: Capabilities,
+    ) -> Result<Option<Arc<RwLock<Player>>>, SendError> {
+        let player = match self
+            .resume_tokens
+            .remove(resume_token)
+            .and_then(|session_id| self.players.get(&session_id))
+        {
+            Some(player) => player.clone(),
+            None => return Ok(None),
+        };
+
+        {
+            let mut player = player.write().await;
+            player.reconnect(tx, remote_ip, protocol_version, capabilities);
+
+            self.resume_tokens
+                .insert(*player.get_resume_token(), *player.get_session_id());
+
+            player.send_handshake().await?;
+            player.flush_pending()?;
+
+            println!("Player {} resumed", player.get_session_id());
+        }
+
+        Ok(Some(player))
+    }
+
+    /// Handles the end of a connection.
+    ///
+    /// Players able to resume their session are kept for the grace period, in which case `true` is
+    /// returned. Other players are removed right away.
+    pub async fn disconnect_player(
+        &mut self,
+        session_id: &Uuid,
+        connection: u32,
+    ) -> Result<bool, ProcessError> {
+        let player = match self.players.get(session_id) {
+            Some(player) => player.clone(),
+            None => return Err(ProcessError::PlayerNotFound),
+        };
+
+        {
+            let mut player = player.write().await;
+
+            // The session was already resumed by another connection
+            if player.get_connection() != connection {
+                return Ok(false);
+            }
+
+            if !self.config.resume_grace_period.is_zero()
+                && player
+                    .get_capabilities()
+                    .contains(Capabilities::SESSION_RESUME)
+            {
+                player.disconnect();
+
+                println!("Player {} disconnected", session_id);
+
+                return Ok(true);
+            }
+        }
+
+        self.remove_player(session_id).await?;
+
+        Ok(false)
+    }
+
+    /// Removes a disconnected player, if its grace period is over.
+    pub async fn expire_player(&mut self, session_id: &Uuid) -> Result<(), ProcessError> {
+        let expired = match self.players.get(session_id) {
+            Some(player) => player
+                .read()
+                .await
+                .get_disconnected_at()
+                .is_some_and(|t| t.elapsed() >= self.config.resume_grace_period),
+            None => false,
+        };
+
+        if expired {
+            self.remove_player(session_id).await?;
+        }
+
+        Ok(())
+    }
+
     pub async fn remove_player(&mut self, session_id: &Uuid) -> Result<(), ProcessError> {
-        match self.players.get(session_id) {
+        match self.players.get(session_id).cloned() {
             Some(player) => {
-                // Delete the room if he is a host
-                let room_to_remove = {
-                    let player = player.read().await;
-
-                    match player.get_room() {
-                        Some(room) => {
-                            let room = room.read().await;
-                            if room.get_host().read().await.get_session_id()
-                                == player.get_session_id()
-                            {
-                                Some(*room.get_code())
-                            } else {
-                                None
-                            }
-                        }
-                        None => None,
-                    }
-                };
-
-                if let Some(code) = room_to_remove {
-                    self.remove_room(&code);
+                if player.read().await.get_room().is_some() {
+                    self.leave_room(&player).await?;
                 }
 
+                self.unqueue(session_id).await;
+
+                self.resume_tokens
+                    .remove(player.read().await.get_resume_token());
                 self.players.remove(session_id);
 
                 println!("Player {} left", session_id);
@@ -88,25 +215,106 @@ impl App {
         }
     }
 
-    pub fn remove_room(&mut self, code: &Code) {
-        self.rooms.remove(code);
+    pub async fn leave_room(&mut self, player: &Arc<RwLock<Player>>) -> Result<(), ProcessError> {
+        let (session_id, room) = {
+            let player = player.read().await;
 
-        println!("Room {} removed", code);
+            match player.get_room() {
+                Some(room) => (*player.get_session_id(), room),
+                None => return Err(ProcessError::NotInRoom),
+            }
+        };
+
+        // Hand the room over to another member if the player was its host, or delete it
+        let room_to_remove = {
+            let mut room = room.write().await;
+            room.remove_player(&session_id).await;
+
+            if room.is_host(&session_id) {
+                match room.next_host().await {
+                    Some(next_host) => {
+                        room.set_host(&next_host).await?;
+                        None
+                    }
+                    None => Some(*room.get_code()),
+                }
+            } else {
+                None
+            }
+        };
+
+        if let Some(code) = room_to_remove {
+            self.remove_room(&code, RoomCloseReason::HostLeft).await;
+        }
+
+        player.write().await.leave_room();
+
+        println!(
+            "Player {} left room {}",
+            session_id,
+            room.read().await.get_code()
+        );
+
+        Ok(())
     }
 
-    pub async fn create_room(&mut self, host: &Arc<RwLock<Player>>) -> Result<(), ProcessError> {
-        if host.read().await.is_in_room() {
-            return Err(ProcessError::InvalidOperation);
+    pub async fn remove_room(&mut self, code: &Code, reason: RoomCloseReason) {
+        let room = match self.rooms.remove(code) {
+            Some(room) => room,
+            None => return,
+        };
+
+        // Release the room before locking its members
+        let members = room.write().await.drain_players();
+
+        for member in members {
+            let mut member = member.write().await;
+            member.leave_room();
+
+            if let Err(e) = member
+                .send(&s2c::Message::RoomClosed {
+                    code: *code,
+                    reason,
+                })
+                .await
+            {
+                eprintln!(
+                    "failed to notify player {} of room closing: {:?}",
+                    member.get_session_id(),
+                    e
+                );
+            }
         }
 
-        let room = Room::new(host).await;
-        let code = *room.get_code();
+        println!("Room {} removed", code);
+    }
+
+    pub async fn create_room(
+        &mut self,
+        host: &Arc<RwLock<Player>>,
+        options: &RoomOptions<'_>,
+        request_id: Option<RequestId>,
+    ) -> Result<(), ProcessError> {
+        let session_id = {
+            let host = host.read().await;
+            if host.is_in_room() {
+                return Err(ProcessError::InvalidOperation);
+            }
+
+            *host.get_session_id()
+        };
+
+        let code = self.allocate_code(options.code)?;
+
+        self.unqueue(&session_id).await;
+
+        let room = Room::new(code, host, options, &self.config).await;
 
         let room = Arc::new(RwLock::new(room));
 
         {
             let mut host = host.write().await;
-            host.send(&s2c::Message::RoomCreated { code: &code })
+            host.send(&s2c::Message::RoomCreated { code, request_id })
                 .await?;
             host.set_room_unchecked(&room);
         }
@@ -117,4 +325,523 @@ impl App {
 
         Ok(())
     }
+
+    /// Adds a player to the room with a code, as typed by the player.
+    ///
+    /// Only the room is locked while the player joins it, the app itself is only locked briefly to
+    /// find the room and take the player out of its queue.
+    pub async fn join_room(
+        app: &RwLock<App>,
+        player: &Arc<RwLock<Player>>,
+        code: &str,
+        password: Option<&[u8]>,
+        role: MemberRole,
+        request_id: Option<RequestId>,
+    ) -> Result<(), ProcessError> {
+        let session_id = {
+            let player = player.read().await;
+            if player.is_in_room() {
+                return Err(ProcessError::InvalidOperation);
+            }
+
+            *player.get_session_id()
+        };
+
+        let room = {
+            let app = app.read().await;
+            let code = app.config.code_format.parse(code)?;
+            match app.rooms.get(&code) {
+                Some(room) => room.clone(),
+                None => return Err(ProcessError::RoomNotFound),
+            }
+        };
+
+        {
+            // The room stays locked until the player received its state, so that no live message
+            // reaches the player before it
+            let mut room_guard = room.write().await;
+            room_guard.add_player(player, password, role).await?;
+
+            let members = room_guard.get_members().await;
+            player
+                .write()
+                .await
+                .enter_room(&room, &room_guard, &members, request_id)
+                .await?;
+        }
+
+        app.write().await.unqueue(&session_id).await;
+
+        Ok(())
+    }
+
+    /// Adds a player to the fullest open public room matching a filter, or creates a public room
+    /// for `max_players` with the filter's metadata prefix as metadata if there is none.
+    pub async fn quick_join(
+        &mut self,
+        player: &Arc<RwLock<Player>>,
+        filter: &RoomFilter<'_>,
+        max_players: u8,
+        request_id: Option<RequestId>,
+    ) -> Result<(), ProcessError> {
+        if player.read().await.is_in_room() {
+            return Err(ProcessError::InvalidOperation);
+        }
+
+        let mut candidates = vec![];
+        for room in self.find_rooms(filter).await {
+            let (is_open, player_count) = {
+                let room = room.read().await;
+                (room.is_open(), room.get_player_count())
+            };
+
+            if is_open {
+                candidates.push((player_count, room));
+            }
+        }
+        candidates.sort_by_key(|(player_count, _)| Reverse(*player_count));
+
+        for (_, room) in candidates {
+            {
+                // The player may be banned from the room
+                let mut room_guard = room.write().await;
+                if room_guard
+                    .add_player(player, None, MemberRole::Player)
+                    .await
+                    .is_err()
+                {
+                    continue;
+                }
+
+                let members = room_guard.get_members().await;
+                player
+                    .write()
+                    .await
+                    .enter_room(&room, &room_guard, &members, request_id)
+                    .await?;
+            }
+
+            let session_id = *player.read().await.get_session_id();
+            self.unqueue(&session_id).await;
+
+            return Ok(());
+        }
+
+        let options = RoomOptions {
+            max_players,
+            public: true,
+            metadata: filter.metadata_prefix,
+            ..RoomOptions::default()
+        };
+        self.create_room(player, &options, request_id).await
+    }
+
+    /// Puts a player in a matchmaking queue, and forms a room once enough compatible players are
+    /// waiting.
+    pub async fn enter_queue(
+        &mut self,
+        player: &Arc<RwLock<Player>>,
+        key: QueueKey,
+    ) -> Result<(), ProcessError> {
+        let session_id = {
+            let player = player.read().await;
+            if key.party_size == 0 || player.is_in_room() {
+                return Err(ProcessError::InvalidOperation);
+            }
+
+            *player.get_session_id()
+        };
+
+        if self.matchmaker.is_queued(&session_id) {
+            return Err(ProcessError::InvalidOperation);
+        }
+
+        self.matchmaker
+            .enter(key.clone(), session_id, player.clone());
+
+        if self.matchmaker.get_queue(&key).count() >= key.party_size as usize {
+            let code = match self.allocate_code(None) {
+                Ok(code) => code,
+                Err(e) => {
+                    self.matchmaker.leave(&session_id);
+                    self.notify_queue(&key).await;
+                    return Err(e);
+                }
+            };
+
+            if let Some(party) = self.matchmaker.take_party(&key) {
+                self.form_room(code, &key, party).await;
+            }
+        }
+
+        self.notify_queue(&key).await;
+
+        Ok(())
+    }
+
+    pub async fn leave_queue(&mut self, session_id: &Uuid) -> Result<(), ProcessError> {
+        let key = self
+            .matchmaker
+            .leave(session_id)
+            .ok_or(ProcessError::NotInQueue)?;
+
+        self.notify_queue(&key).await;
+
+        Ok(())
+    }
+
+    /// Takes a player out of its matchmaking queue, if it is waiting in one, as it enters a room or
+    /// leaves.
+    async fn unqueue(&mut self, session_id: &Uuid) {
+        if let Some(key) = self.matchmaker.leave(session_id) {
+            self.notify_queue(&key).await;
+        }
+    }
+
+    /// Sends their position to the players waiting in a queue.
+    async fn notify_queue(&self, key: &QueueKey) {
+        let waiting = self.matchmaker.get_queue(key).count();
+
+        for (i, (session_id, player)) in self.matchmaker.get_queue(key).enumerate() {
+            let msg = s2c::Message::QueuePosition {
+                position: (i + 1).try_into().unwrap_or(u16::MAX),
+                waiting: waiting.try_into().unwrap_or(u16::MAX),
+            };
+
+            if let Err(e) = player.read().await.send(&msg).await {
+                eprintln!(
+                    "failed to notify player {} of its queue position: {:?}",
+                    session_id, e
+                );
+            }
+        }
+    }
+
+    /// Creates a room for a party formed by the matchmaker, hosted by the player who waited the
+    /// longest.
+    async fn form_room(&mut self, code: Code, key: &QueueKey, party: Vec<Arc<RwLock<Player>>>) {
+        let options = RoomOptions {
+            host_migration: HostMigration::LongestConnected,
+            max_players: key.party_size,
+            ..RoomOptions::default()
+        };
+        let room = Room::new(code, &party[0], &options, &self.config).await;
+
+        let room = Arc::new(RwLock::new(room));
+
+        self.rooms.insert(code, room.clone());
+
+        let mut room_guard = room.write().await;
+        for player in &party[1..] {
+            room_guard.add_player_unchecked(player).await;
+        }
+
+        let members = room_guard.get_members().await;
+        for player in &party {
+            let mut player = player.write().await;
+            if let Err(e) = player.enter_room(&room, &room_guard, &members, None).await {
+                eprintln!(
+                    "failed to add player {} to matched room {}: {:?}",
+                    player.get_session_id(),
+                    code,
+                    e
+                );
+            }
+        }
+
+        println!("Room {} formed from queue {}", code, key.name);
+    }
+
+    /// Picks the code of a new room: the requested one if it is free, or a random one not used by
+    /// any live room.
+    fn allocate_code(&self, requested: Option<&str>) -> Result<Code, ProcessError> {
+        if self
+            .config
+            .max_rooms
+            .is_some_and(|max_rooms| self.rooms.len() >= max_rooms)
+        {
+            return Err(ProcessError::TooManyRooms);
+        }
+
+        let code_format = &self.config.code_format;
+
+        if let Some(requested) = requested {
+            let code = code_format.complete(requested)?;
+            if self.rooms.contains_key(&code) {
+                return Err(ProcessError::CodeInUse);
+            }
+            return Ok(code);
+        }
+
+        if self.rooms.len() as f64 >= code_format.code_space() as f64 * MAX_CODE_SPACE_USAGE {
+            return Err(ProcessError::CodesExhausted);
+        }
+
+        (0..MAX_CODE_ATTEMPTS)
+            .map(|_| code_format.generate())
+            .find(|code| !self.rooms.contains_key(code))
+            .ok_or(ProcessError::CodesExhausted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::app::testing::{add_player, session_id};
+    use crate::code::CodeFormat;
+
+    #[tokio::test]
+    async fn create_room_never_reuses_a_live_code() {
+        let mut app = App::with_config(Config {
+            code_format: CodeFormat::new("AB", 2, false).unwrap(),
+            ..Config::default()
+        });
+        let options = RoomOptions::default();
+
+        let mut hosts = vec![];
+        for _ in 0..2 {
+            let (host, rx) = add_player(&mut app).await;
+            app.create_room(&host, &options, None).await.unwrap();
+            hosts.push((host, rx));
+        }
+        assert_eq!(2, app.rooms.len());
+
+        let (host, _rx) = add_player(&mut app).await;
+        assert!(matches!(
+            app.create_room(&host, &options, None).await,
+            Err(ProcessError::CodesExhausted)
+        ));
+        assert_eq!(2, app.rooms.len());
+    }
+
+    #[tokio::test]
+    async fn create_room_with_requested_code() {
+        let mut app = App::new();
+        let code = app.config.code_format.generate();
+        let options = RoomOptions {
+            code: Some(code.as_str()),
+            ..RoomOptions::default()
+        };
+
+        let (host, _host_rx) = add_player(&mut app).await;
+        app.create_room(&host, &options, None).await.unwrap();
+        let room = app.get_room(&code).unwrap().clone();
+
+        let (other, _other_rx) = add_player(&mut app).await;
+        assert!(matches!(
+            app.create_room(&other, &options, None).await,
+            Err(ProcessError::CodeInUse)
+        ));
+        assert!(Arc::ptr_eq(&room, app.get_room(&code).unwrap()));
+
+        // The check character may be omitted
+        let options = RoomOptions {
+            code: Some(&code.as_str()[..5]),
+            ..RoomOptions::default()
+        };
+        assert!(matches!(
+            app.create_room(&other, &options, None).await,
+            Err(ProcessError::CodeInUse)
+        ));
+    }
+
+    #[tokio::test]
+    async fn enter_queue_forms_a_room_for_a_full_party() {
+        let mut app = App::new();
+        let key = QueueKey {
+            name: "ranked".to_string(),
+            party_size: 2,
+            attributes: b"eu".to_vec(),
+        };
+
+        let (first, _first_rx) = add_player(&mut app).await;
+        let (other, _other_rx) = add_player(&mut app).await;
+        let (second, _second_rx) = add_player(&mut app).await;
+
+        app.enter_queue(&first, key.clone()).await.unwrap();
+        app.enter_queue(
+            &other,
+            QueueKey {
+                attributes: b"us".to_vec(),
+                ..key.clone()
+            },
+        )
+        .await
+        .unwrap();
+        assert!(app.rooms.is_empty());
+
+        app.enter_queue(&second, key).await.unwrap();
+        assert_eq!(1, app.rooms.len());
+
+        let room = first.read().await.get_room().unwrap();
+        assert!(Arc::ptr_eq(&room, &second.read().await.get_room().unwrap()));
+        assert!(room
+            .read()
+            .await
+            .is_host(first.read().await.get_session_id()));
+        assert!(!other.read().await.is_in_room());
+    }
+
+    #[tokio::test]
+    async fn joining_a_room_leaves_the_queue() {
+        let app = RwLock::new(App::new());
+        let (host, _host_rx) = add_player(&mut *app.write().await).await;
+        let (player, _player_rx) = add_player(&mut *app.write().await).await;
+        let session_id = session_id(&player).await;
+        let key = QueueKey {
+            name: "duel".to_string(),
+            party_size: 2,
+            attributes: vec![],
+        };
+
+        let options = RoomOptions {
+            public: true,
+            ..RoomOptions::default()
+        };
+        app.write()
+            .await
+            .create_room(&host, &options, None)
+            .await
+            .unwrap();
+        let code = *host
+            .read()
+            .await
+            .get_room()
+            .unwrap()
+            .read()
+            .await
+            .get_code();
+
+        app.write()
+            .await
+            .enter_queue(&player, key.clone())
+            .await
+            .unwrap();
+        App::join_room(&app, &player, code.as_str(), None, MemberRole::Player, None)
+            .await
+            .unwrap();
+
+        let mut app = app.write().await;
+        assert!(!app.matchmaker.is_queued(&session_id));
+        app.leave_room(&player).await.unwrap();
+
+        app.enter_queue(&player, key.clone()).await.unwrap();
+        app.quick_join(&player, &RoomFilter::default(), 0, None)
+            .await
+            .unwrap();
+        assert!(!app.matchmaker.is_queued(&session_id));
+        app.leave_room(&player).await.unwrap();
+
+        app.enter_queue(&player, key.clone()).await.unwrap();
+        assert_eq!(1, app.matchmaker.get_queue(&key).count());
+    }
+
+    #[tokio::test]
+    async fn quick_join_fills_open_rooms() {
+        let mut app = App::new();
+        let filter = RoomFilter {
+            include_full: false,
+            metadata_prefix: b"party",
+        };
+
+        let (first, _first_rx) = add_player(&mut app).await;
+        app.quick_join(&first, &filter, 2, None).await.unwrap();
+        assert_eq!(1, app.rooms.len());
+
+        let (second, _second_rx) = add_player(&mut app).await;
+        app.quick_join(&second, &filter, 2, None).await.unwrap();
+        assert_eq!(1, app.rooms.len());
+
+        let room = first.read().await.get_room().unwrap();
+        assert!(Arc::ptr_eq(&room, &second.read().await.get_room().unwrap()));
+        assert_eq!(b"party", room.read().await.get_metadata());
+        assert!(room.read().await.is_full());
+
+        // Once the room is full, the next player gets a new one
+        let (third, _third_rx) = add_player(&mut app).await;
+        app.quick_join(&third, &filter, 2, None).await.unwrap();
+        assert_eq!(2, app.rooms.len());
+
+        let other = third.read().await.get_room().unwrap();
+        assert!(!Arc::ptr_eq(&room, &other));
+        assert_eq!(2, other.read().await.get_max_players());
+        assert_eq!(2, room.read().await.get_player_count());
+    }
+
+    #[tokio::test]
+    async fn resume_player_delivers_pending_messages() {
+        let mut app = App::new();
+
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let player = app
+            .add_player(Player::new(tx, None, 1, Capabilities::ALL))
+            .await
+            .unwrap();
+        let (session_id, resume_token) = {
+            let player = player.read().await;
+            (*player.get_session_id(), *player.get_resume_token())
+        };
+
+        assert!(app.disconnect_player(&session_id, 0).await.unwrap());
+
+        player
+            .read()
+            .await
+            .send(&s2c::Message::Ack { request_id: 1 })
+            .await
+            .unwrap();
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let resumed = app
+            .resume_player(&resume_token, tx, None, 1, Capabilities::ALL)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(session_id, *resumed.read().await.get_session_id());
+        assert!(!app.resume_tokens.contains_key(&resume_token));
+
+        let mut received = vec![];
+        while let Ok(buf) = rx.try_recv() {
+            received.push(buf);
+        }
+
+        // Hello, AssignSessionId, then the pending Ack
+        assert_eq!(3, received.len());
+        assert_eq!(vec![10, 0, 0, 0, 1], received[2]);
+    }
+
+    #[tokio::test]
+    async fn resume_player_takes_over_a_live_session() {
+        let mut app = App::new();
+        let (player, mut old_rx) = add_player(&mut app).await;
+        let (connection, resume_token) = {
+            let player = player.read().await;
+            (player.get_connection(), *player.get_resume_token())
+        };
+
+        let (tx, _rx) = mpsc::unbounded_channel();
+        app.resume_player(&resume_token, tx, None, 1, Capabilities::ALL)
+            .await
+            .unwrap()
+            .unwrap();
+
+        // The old connection notices it was replaced, and receives nothing more
+        assert_ne!(connection, player.read().await.get_connection());
+        while old_rx.try_recv().is_ok() {}
+        assert!(matches!(
+            old_rx.try_recv(),
+            Err(mpsc::error::TryRecvError::Disconnected)
+        ));
+
+        // The old connection ending doesn't disconnect the session
+        let session_id = session_id(&player).await;
+        assert!(!app
+            .disconnect_player(&session_id, connection)
+            .await
+            .unwrap());
+        assert!(player.read().await.get_disconnected_at().is_none());
+    }
 }
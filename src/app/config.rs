@@ -0,0 +1,37 @@
+use std::time::Duration;
+
+use crate::code::CodeFormat;
+
+/// Settings of an [`App`](crate::app::App).
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// How long a disconnected player is kept, waiting for its client to resume the session.
+    ///
+    /// Zero disables session resumption.
+    pub resume_grace_period: Duration,
+    /// Format of the codes given to new rooms, and expected from players joining one.
+    pub code_format: CodeFormat,
+    /// Maximum number of rooms open at once, `None` for no limit.
+    pub max_rooms: Option<usize>,
+    /// Countdown between the host starting the game of a room and the game beginning.
+    pub game_start_delay: Duration,
+    /// Maximum number of payloads kept in the history of a room, whatever its host asked for.
+    ///
+    /// Zero disables room histories.
+    pub max_history_len: usize,
+    /// Maximum total size of the payloads kept in the history of a room, in bytes.
+    pub max_history_bytes: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            resume_grace_period: Duration::from_secs(30),
+            code_format: CodeFormat::default(),
+            max_rooms: None,
+            game_start_delay: Duration::from_secs(3),
+            max_history_len: 256,
+            max_history_bytes: 64 * 1024,
+        }
+    }
+}
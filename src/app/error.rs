@@ -1,6 +1,7 @@
 use thiserror::Error;
 use tokio::sync::mpsc;
 
+use crate::code::CodeError;
 use crate::proto;
 
 #[derive(Error, Debug)]
@@ -17,6 +18,64 @@ pub enum ProcessError {
     PlayerNotFound,
     #[error("room not found")]
     RoomNotFound,
+    #[error("room full")]
+    RoomFull,
+    #[error("wrong password")]
+    WrongPassword,
+    #[error("banned from room")]
+    Banned,
+    #[error("invalid room code: {0}")]
+    InvalidCode(#[from] CodeError),
+    #[error("too many rooms")]
+    TooManyRooms,
+    #[error("no room code available")]
+    CodesExhausted,
+    #[error("room code in use")]
+    CodeInUse,
+    #[error("too many room values")]
+    TooManyRoomValues,
+    #[error("room value too long")]
+    RoomValueTooLong,
+    #[error("too many player data entries")]
+    TooManyPlayerDataEntries,
+    #[error("player data too long")]
+    PlayerDataTooLong,
+    #[error("not every player is ready")]
+    NotAllReady,
+    #[error("not in queue")]
+    NotInQueue,
+    #[error("too many subscriptions")]
+    TooManySubscriptions,
+}
+
+impl ProcessError {
+    /// Stable numeric code sent to clients in [`s2c::Message::Error`](crate::proto::s2c::Message).
+    ///
+    /// Codes below 100 are decode errors, see [`DecodeError::code`](proto::error::DecodeError::code).
+    pub const fn code(&self) -> u16 {
+        match self {
+            ProcessError::Decode(e) => e.code(),
+            ProcessError::Send(_) => 100,
+            ProcessError::InvalidOperation => 101,
+            ProcessError::NotInRoom => 102,
+            ProcessError::PlayerNotFound => 103,
+            ProcessError::RoomNotFound => 104,
+            ProcessError::RoomFull => 105,
+            ProcessError::WrongPassword => 106,
+            ProcessError::Banned => 107,
+            ProcessError::InvalidCode(_) => 108,
+            ProcessError::TooManyRooms => 109,
+            ProcessError::CodesExhausted => 110,
+            ProcessError::CodeInUse => 111,
+            ProcessError::TooManyRoomValues => 112,
+            ProcessError::RoomValueTooLong => 113,
+            ProcessError::TooManyPlayerDataEntries => 114,
+            ProcessError::PlayerDataTooLong => 115,
+            ProcessError::NotAllReady => 116,
+            ProcessError::NotInQueue => 117,
+            ProcessError::TooManySubscriptions => 118,
+        }
+    }
 }
 
 #[derive(Error, Debug)]
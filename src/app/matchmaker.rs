@@ -0,0 +1,83 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::app::Player;
+
+/// Players entering a queue with the same key are compatible with each other.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct QueueKey {
+    pub name: String,
+    /// Number of players in the rooms formed from the queue.
+    pub party_size: u8,
+    pub attributes: Vec<u8>,
+}
+
+/// Player waiting in a queue, with its session id.
+type QueueEntry = (Uuid, Arc<RwLock<Player>>);
+
+/// Players waiting to be matched, in the order they entered their queue.
+#[derive(Default)]
+pub struct Matchmaker {
+    queues: HashMap<QueueKey, VecDeque<QueueEntry>>,
+    /// Queue of every waiting player.
+    entries: HashMap<Uuid, QueueKey>,
+}
+
+impl Matchmaker {
+    pub fn is_queued(&self, session_id: &Uuid) -> bool {
+        self.entries.contains_key(session_id)
+    }
+
+    pub fn enter(&mut self, key: QueueKey, session_id: Uuid, player: Arc<RwLock<Player>>) {
+        self.entries.insert(session_id, key.clone());
+        self.queues
+            .entry(key)
+            .or_default()
+            .push_back((session_id, player));
+    }
+
+    /// Removes a player from its queue, returning the key of the queue.
+    pub fn leave(&mut self, session_id: &Uuid) -> Option<QueueKey> {
+        let key = self.entries.remove(session_id)?;
+
+        if let Some(queue) = self.queues.get_mut(&key) {
+            queue.retain(|(id, _)| id != session_id);
+            if queue.is_empty() {
+                self.queues.remove(&key);
+            }
+        }
+
+        Some(key)
+    }
+
+    /// Players waiting in a queue, the first one having waited the longest.
+    pub fn get_queue(&self, key: &QueueKey) -> impl Iterator<Item = &QueueEntry> {
+        self.queues.get(key).into_iter().flatten()
+    }
+
+    /// Removes the players of a full party from a queue, if enough of them are waiting.
+    pub fn take_party(&mut self, key: &QueueKey) -> Option<Vec<Arc<RwLock<Player>>>> {
+        let queue = self.queues.get_mut(key)?;
+        if queue.len() < key.party_size as usize {
+            return None;
+        }
+
+        let party: Vec<_> = queue.drain(..key.party_size as usize).collect();
+        if queue.is_empty() {
+            self.queues.remove(key);
+        }
+
+        Some(
+            party
+                .into_iter()
+                .map(|(session_id, player)| {
+                    self.entries.remove(&session_id);
+                    player
+                })
+                .collect(),
+        )
+    }
+}
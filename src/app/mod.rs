@@ -1,36 +1,63 @@
 use std::sync::Arc;
 
 use tokio::sync::RwLock;
+use tokio::time::sleep;
+use uuid::Uuid;
 
 pub use app::App;
-pub use player::Player;
-pub use room::Room;
+pub use config::Config;
+pub use matchmaker::{Matchmaker, QueueKey};
+pub use player::{Player, PlayerData};
+pub use room::{Member, Room};
 
 use crate::app::error::ProcessError;
-use crate::proto::{c2s, s2c};
+use crate::proto::s2c::MemberRole;
+use crate::proto::{c2s, s2c, ForwardMessage};
 
+#[allow(clippy::module_inception)]
 mod app;
+mod config;
 pub mod error;
+mod matchmaker;
 mod player;
 mod room;
+#[cfg(test)]
+mod testing;
+
+/// Maximum number of rooms in a `RoomList`.
+const MAX_LISTED_ROOMS: usize = 100;
 
 pub async fn process_message(
     sender: &Arc<RwLock<Player>>,
     app: &Arc<RwLock<App>>,
     msg: &[u8],
 ) -> Result<(), ProcessError> {
-    let msg = c2s::Message::decode(msg)?;
+    let c2s::Request {
+        request_id,
+        message,
+    } = c2s::Request::decode(msg)?;
 
-    match msg {
+    match message {
         c2s::Message::SendToPlayer(mut fwd) => {
             let sender = sender.read().await;
             let sender_session_id = *sender.get_session_id();
 
             let receiver = match sender.get_room() {
-                Some(room) => match room.read().await.get_player(&fwd.session_id) {
-                    Some(player) => player,
-                    None => return Err(ProcessError::PlayerNotFound),
-                },
+                Some(room) => {
+                    let room = room.read().await;
+                    let receiver = match room.get_player(&fwd.session_id) {
+                        Some(player) => player,
+                        None => return Err(ProcessError::PlayerNotFound),
+                    };
+
+                    // Spectators may only send to each other
+                    if room.is_spectator(&sender_session_id) && !room.is_spectator(&fwd.session_id)
+                    {
+                        return Err(ProcessError::InvalidOperation);
+                    }
+
+                    receiver
+                }
                 None => return Err(ProcessError::NotInRoom),
             };
 
@@ -48,18 +75,724 @@ pub async fn process_message(
                 .await
                 .send(&s2c::Message::ReceiveFromPlayer(fwd))
                 .await?;
+        }
+        c2s::Message::BroadcastToRoom { raw } => {
+            let (sender_session_id, room) = get_room(sender).await?;
+
+            let fwd = ForwardMessage {
+                session_id: sender_session_id,
+                raw,
+            };
+            let msg = s2c::Message::ReceiveFromPlayer(fwd);
+
+            // Recorded and broadcast under the same lock, so joining players never receive a
+            // payload both live and from the history
+            let mut room = room.write().await;
+            if room.is_spectator(&sender_session_id) {
+                // Spectators only reach each other
+                for spectator in room.get_spectators() {
+                    let spectator = spectator.read().await;
+                    if spectator.get_session_id() == &sender_session_id {
+                        continue;
+                    }
+
+                    if let Err(e) = spectator.send(&msg).await {
+                        eprintln!("broadcast error(room={}): {:?}", room.get_code(), e);
+                    }
+                }
+            } else {
+                room.record_history(&sender_session_id, raw);
+                room.broadcast_except(&msg, &sender_session_id).await;
+            }
+        }
+        c2s::Message::BroadcastToSpectators { raw } => {
+            let (sender_session_id, room) = get_room(sender).await?;
+
+            let room = room.read().await;
+            if !room.is_host(&sender_session_id) {
+                return Err(ProcessError::InvalidOperation);
+            }
+
+            let fwd = ForwardMessage {
+                session_id: sender_session_id,
+                raw,
+            };
+            let msg = s2c::Message::ReceiveFromPlayer(fwd);
+
+            for spectator in room.get_spectators() {
+                if let Err(e) = spectator.read().await.send(&msg).await {
+                    eprintln!("broadcast error(room={}): {:?}", room.get_code(), e);
+                }
+            }
+        }
+        c2s::Message::Subscribe { topic } => {
+            let (sender_session_id, room) = get_room(sender).await?;
+            room.write().await.subscribe(&sender_session_id, topic)?;
+        }
+        c2s::Message::Unsubscribe { topic } => {
+            let (sender_session_id, room) = get_room(sender).await?;
+            room.write().await.unsubscribe(&sender_session_id, topic);
+        }
+        c2s::Message::PublishToTopic { topic, raw } => {
+            let (sender_session_id, room) = get_room(sender).await?;
+
+            let fwd = ForwardMessage {
+                session_id: sender_session_id,
+                raw,
+            };
+            let msg = s2c::Message::ReceiveFromPlayer(fwd);
+
+            let room = room.read().await;
+            let from_spectator = room.is_spectator(&sender_session_id);
+            for (session_id, subscriber) in room.get_subscribers(topic) {
+                // Spectators only reach each other, like with BroadcastToRoom
+                if session_id == &sender_session_id
+                    || (from_spectator && !room.is_spectator(session_id))
+                {
+                    continue;
+                }
+
+                if let Err(e) = subscriber.read().await.send(&msg).await {
+                    eprintln!("broadcast error(room={}): {:?}", room.get_code(), e);
+                }
+            }
+        }
+        c2s::Message::Multicast { targets, raw } => {
+            let (sender_session_id, room) = get_room(sender).await?;
+
+            let fwd = ForwardMessage {
+                session_id: sender_session_id,
+                raw,
+            };
+            let msg = s2c::Message::ReceiveFromPlayer(fwd);
+
+            // Deliver to every target found, before reporting the missing ones
+            let mut result = Ok(());
+            {
+                let room = room.read().await;
+                if room.is_spectator(&sender_session_id)
+                    && targets.iter().any(|target| {
+                        room.get_player(target).is_some() && !room.is_spectator(target)
+                    })
+                {
+                    return Err(ProcessError::InvalidOperation);
+                }
+
+                for target in targets {
+                    match room.get_player(target) {
+                        Some(receiver) => {
+                            if let Err(e) = receiver.read().await.send(&msg).await {
+                                eprintln!("multicast error(room={}): {:?}", room.get_code(), e);
+                            }
+                        }
+                        None => result = Err(ProcessError::PlayerNotFound),
+                    }
+                }
+            }
+            result?;
+        }
+        c2s::Message::CreateRoom(options) => {
+            return app
+                .write()
+                .await
+                .create_room(sender, &options, request_id)
+                .await;
+        }
+        c2s::Message::JoinRoom {
+            code,
+            password,
+            spectator,
+        } => {
+            let role = if spectator {
+                MemberRole::Spectator
+            } else {
+                MemberRole::Player
+            };
+
+            return App::join_room(app, sender, code, password, role, request_id).await;
+        }
+        c2s::Message::LeaveRoom => app.write().await.leave_room(sender).await?,
+        c2s::Message::TransferHost { session_id } => {
+            let (sender_session_id, room) = get_room(sender).await?;
+
+            let mut room = room.write().await;
+            if !room.is_host(&sender_session_id) {
+                return Err(ProcessError::InvalidOperation);
+            }
+
+            room.set_host(session_id).await?;
+        }
+        c2s::Message::KickPlayer { session_id, reason } => {
+            let (sender_session_id, room) = get_room(sender).await?;
+
+            {
+                let room = room.read().await;
+                if !room.is_host(&sender_session_id) || room.is_host(session_id) {
+                    return Err(ProcessError::InvalidOperation);
+                }
+            }
+
+            kick_player(&room, session_id, reason).await?;
+        }
+        c2s::Message::BanPlayer { session_id, by_ip } => {
+            let (sender_session_id, room) = get_room(sender).await?;
+
+            let ip = match app.read().await.get_player(session_id) {
+                Some(player) if by_ip => player.read().await.get_remote_ip().copied(),
+                _ => None,
+            };
+
+            let is_member = {
+                let mut room = room.write().await;
+                if !room.is_host(&sender_session_id) || room.is_host(session_id) {
+                    return Err(ProcessError::InvalidOperation);
+                }
+
+                room.ban(session_id, ip);
+                room.get_player(session_id).is_some()
+            };
+
+            if is_member {
+                kick_player(&room, session_id, &[]).await?;
+            }
+
+            println!(
+                "Player {} banned from room {}",
+                session_id,
+                room.read().await.get_code()
+            );
+        }
+        c2s::Message::UnbanPlayer { session_id } => {
+            let (sender_session_id, room) = get_room(sender).await?;
+
+            let mut room = room.write().await;
+            if !room.is_host(&sender_session_id) {
+                return Err(ProcessError::InvalidOperation);
+            }
+
+            room.unban(session_id)?;
+        }
+        c2s::Message::SetRoomValue { key, value } => {
+            let (sender_session_id, room) = get_room(sender).await?;
+
+            let mut room = room.write().await;
+            if !room.can_set_values(&sender_session_id) {
+                return Err(ProcessError::InvalidOperation);
+            }
+
+            room.set_value(key, value).await?;
+        }
+        c2s::Message::DeleteRoomValue { key } => {
+            let (sender_session_id, room) = get_room(sender).await?;
+
+            let mut room = room.write().await;
+            if !room.can_set_values(&sender_session_id) {
+                return Err(ProcessError::InvalidOperation);
+            }
+
+            room.delete_value(key).await;
+        }
+        c2s::Message::SetPlayerData { key, value } => {
+            let (session_id, room) = {
+                let mut sender = sender.write().await;
+                sender.set_data(key, value)?;
+                (*sender.get_session_id(), sender.get_room())
+            };
+
+            if let Some(room) = room {
+                room.read()
+                    .await
+                    .broadcast(&s2c::Message::PlayerDataChanged {
+                        player_session_id: &session_id,
+                        key,
+                        value: Some(value).filter(|v| !v.is_empty()),
+                    })
+                    .await;
+            }
+        }
+        c2s::Message::SetReady { ready } => {
+            let (sender_session_id, room) = get_room(sender).await?;
+
+            room.write()
+                .await
+                .set_ready(&sender_session_id, ready)
+                .await?;
+        }
+        c2s::Message::StartGame => {
+            let delay = app.read().await.get_config().game_start_delay;
+            let (sender_session_id, room) = get_room(sender).await?;
+
+            let start_at = {
+                let mut room = room.write().await;
+                if !room.is_host(&sender_session_id) {
+                    return Err(ProcessError::InvalidOperation);
+                }
+
+                room.start_game(delay).await?
+            };
+
+            // Move the room in game at the end of the countdown, unless it is closed by then
+            let room = Arc::downgrade(&room);
+            tokio::spawn(async move {
+                sleep(delay).await;
+
+                if let Some(room) = room.upgrade() {
+                    room.write().await.begin_game(start_at).await;
+                }
+            });
+        }
+        c2s::Message::EndGame => {
+            let (sender_session_id, room) = get_room(sender).await?;
+
+            let mut room = room.write().await;
+            if !room.is_host(&sender_session_id) {
+                return Err(ProcessError::InvalidOperation);
+            }
 
-            Ok(())
+            room.end_game().await?;
         }
-        c2s::Message::CreateRoom => app.write().await.create_room(&sender).await,
-        c2s::Message::JoinRoom { code } => {
-            let room = match app.read().await.get_room(code) {
-                Some(room) => room.clone(),
-                None => return Err(ProcessError::RoomNotFound),
+        c2s::Message::EnterQueue {
+            queue_name,
+            party_size,
+            attributes,
+        } => {
+            let key = QueueKey {
+                name: queue_name.to_string(),
+                party_size,
+                attributes: attributes.to_vec(),
             };
+            app.write().await.enter_queue(sender, key).await?;
+        }
+        c2s::Message::LeaveQueue => {
+            let session_id = *sender.read().await.get_session_id();
+            app.write().await.leave_queue(&session_id).await?;
+        }
+        c2s::Message::QuickJoin {
+            filter,
+            max_players,
+        } => {
+            return app
+                .write()
+                .await
+                .quick_join(sender, &filter, max_players, request_id)
+                .await;
+        }
+        c2s::Message::ListRooms { filter } => {
+            let rooms = app.read().await.find_rooms(&filter).await;
 
-            room.write().await.add_player(sender).await;
-            sender.write().await.enter_room(&room).await
+            let mut guards = Vec::with_capacity(rooms.len().min(MAX_LISTED_ROOMS));
+            for room in rooms.iter().take(MAX_LISTED_ROOMS) {
+                guards.push(room.read().await);
+            }
+
+            return Ok(sender
+                .read()
+                .await
+                .send(&s2c::Message::RoomList {
+                    rooms: guards.iter().map(|room| room.get_info()).collect(),
+                    request_id,
+                })
+                .await?);
+        }
+        // The handshake is already over
+        c2s::Message::Hello { .. } => return Err(ProcessError::InvalidOperation),
+    }
+
+    if let Some(request_id) = request_id {
+        sender
+            .read()
+            .await
+            .send(&s2c::Message::Ack { request_id })
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Returns the session id of a player, and the room it is in.
+async fn get_room(player: &Arc<RwLock<Player>>) -> Result<(Uuid, Arc<RwLock<Room>>), ProcessError> {
+    let player = player.read().await;
+
+    match player.get_room() {
+        Some(room) => Ok((*player.get_session_id(), room)),
+        None => Err(ProcessError::NotInRoom),
+    }
+}
+
+/// Removes a player from a room, and tells it why.
+async fn kick_player(
+    room: &Arc<RwLock<Room>>,
+    session_id: &Uuid,
+    reason: &[u8],
+) -> Result<(), ProcessError> {
+    let kicked = {
+        let mut room = room.write().await;
+
+        let kicked = match room.get_player(session_id) {
+            Some(player) => player,
+            None => return Err(ProcessError::PlayerNotFound),
+        };
+        room.remove_player(session_id).await;
+
+        kicked
+    };
+
+    // The room is released before locking the kicked player
+    let mut kicked = kicked.write().await;
+    if kicked.get_room().is_some_and(|r| Arc::ptr_eq(&r, room)) {
+        kicked.leave_room();
+    }
+    kicked.send(&s2c::Message::Kicked { reason }).await?;
+
+    println!(
+        "Player {} kicked from room {}",
+        session_id,
+        room.read().await.get_code()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc::UnboundedReceiver;
+
+    use super::*;
+    use crate::app::testing::{add_player, session_id};
+    use crate::proto::c2s::RoomOptions;
+
+    async fn send(
+        app: &Arc<RwLock<App>>,
+        sender: &Arc<RwLock<Player>>,
+        msg: c2s::Message<'_>,
+    ) -> Result<(), ProcessError> {
+        let mut buf = vec![];
+        msg.encode(&mut buf).unwrap();
+
+        process_message(sender, app, &buf).await
+    }
+
+    /// Makes `host` create a room, and the guests join it.
+    async fn create_room(
+        app: &Arc<RwLock<App>>,
+        host: &Arc<RwLock<Player>>,
+        guests: &[&Arc<RwLock<Player>>],
+    ) -> Arc<RwLock<Room>> {
+        send(app, host, c2s::Message::CreateRoom(RoomOptions::default()))
+            .await
+            .unwrap();
+        let room = host.read().await.get_room().unwrap();
+
+        let code = room.read().await.get_code().to_string();
+        for guest in guests {
+            join_room(app, guest, &code, false).await.unwrap();
+        }
+
+        room
+    }
+
+    async fn join_room(
+        app: &Arc<RwLock<App>>,
+        player: &Arc<RwLock<Player>>,
+        code: &str,
+        spectator: bool,
+    ) -> Result<(), ProcessError> {
+        send(
+            app,
+            player,
+            c2s::Message::JoinRoom {
+                code,
+                password: None,
+                spectator,
+            },
+        )
+        .await
+    }
+
+    /// Messages received by a player since the last call.
+    fn received(rx: &mut UnboundedReceiver<Vec<u8>>) -> Vec<Vec<u8>> {
+        std::iter::from_fn(|| rx.try_recv().ok()).collect()
+    }
+
+    #[tokio::test]
+    async fn only_the_host_transfers_host() {
+        let app = Arc::new(RwLock::new(App::new()));
+        let (host, _host_rx) = add_player(&mut *app.write().await).await;
+        let (guest, _guest_rx) = add_player(&mut *app.write().await).await;
+        let room = create_room(&app, &host, &[&guest]).await;
+
+        let host_id = session_id(&host).await;
+        let guest_id = session_id(&guest).await;
+
+        assert!(matches!(
+            send(
+                &app,
+                &guest,
+                c2s::Message::TransferHost {
+                    session_id: &guest_id
+                }
+            )
+            .await,
+            Err(ProcessError::InvalidOperation)
+        ));
+        assert!(room.read().await.is_host(&host_id));
+
+        send(
+            &app,
+            &host,
+            c2s::Message::TransferHost {
+                session_id: &guest_id,
+            },
+        )
+        .await
+        .unwrap();
+        assert!(room.read().await.is_host(&guest_id));
+
+        // Without host migration, the room closes when its host leaves
+        send(&app, &guest, c2s::Message::LeaveRoom).await.unwrap();
+        assert!(host.read().await.get_room().is_none());
+        assert_eq!(0, app.read().await.get_rooms().count());
+    }
+
+    #[tokio::test]
+    async fn only_the_host_kicks_guests() {
+        let app = Arc::new(RwLock::new(App::new()));
+        let (host, mut host_rx) = add_player(&mut *app.write().await).await;
+        let (guest, mut guest_rx) = add_player(&mut *app.write().await).await;
+        let (other, _other_rx) = add_player(&mut *app.write().await).await;
+        let room = create_room(&app, &host, &[&guest, &other]).await;
+
+        let host_id = session_id(&host).await;
+        let guest_id = session_id(&guest).await;
+
+        for (sender, target) in [(&other, &guest_id), (&host, &host_id)] {
+            assert!(matches!(
+                send(
+                    &app,
+                    sender,
+                    c2s::Message::KickPlayer {
+                        session_id: target,
+                        reason: b"",
+                    }
+                )
+                .await,
+                Err(ProcessError::InvalidOperation)
+            ));
         }
+        assert!(room.read().await.get_player(&guest_id).is_some());
+
+        received(&mut host_rx);
+        received(&mut guest_rx);
+        send(
+            &app,
+            &host,
+            c2s::Message::KickPlayer {
+                session_id: &guest_id,
+                reason: b"spam",
+            },
+        )
+        .await
+        .unwrap();
+
+        assert!(room.read().await.get_player(&guest_id).is_none());
+        assert!(guest.read().await.get_room().is_none());
+        assert!(received(&mut guest_rx).iter().any(|buf| matches!(
+            s2c::Message::decode(buf),
+            Ok(s2c::Message::Kicked { reason: b"spam" })
+        )));
+        assert!(received(&mut host_rx).iter().any(|buf| matches!(
+            s2c::Message::decode(buf),
+            Ok(s2c::Message::PlayerLeft { player_session_id }) if player_session_id == &guest_id
+        )));
+    }
+
+    /// Payloads of the `ReceiveFromPlayer` messages received by a player since the last call.
+    fn forwarded(rx: &mut UnboundedReceiver<Vec<u8>>) -> Vec<Vec<u8>> {
+        received(rx)
+            .iter()
+            .filter_map(|buf| match s2c::Message::decode(buf) {
+                Ok(s2c::Message::ReceiveFromPlayer(fwd)) => Some(fwd.raw.to_vec()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn spectators_only_reach_spectators() {
+        let app = Arc::new(RwLock::new(App::new()));
+        let (host, mut host_rx) = add_player(&mut *app.write().await).await;
+        let (guest, mut guest_rx) = add_player(&mut *app.write().await).await;
+        let (first, mut first_rx) = add_player(&mut *app.write().await).await;
+        let (second, mut second_rx) = add_player(&mut *app.write().await).await;
+        let room = create_room(&app, &host, &[&guest]).await;
+
+        let code = room.read().await.get_code().to_string();
+        for spectator in [&first, &second] {
+            join_room(&app, spectator, &code, true).await.unwrap();
+        }
+
+        let host_id = session_id(&host).await;
+        let second_id = session_id(&second).await;
+
+        assert!(matches!(
+            send(
+                &app,
+                &first,
+                c2s::Message::SendToPlayer(ForwardMessage {
+                    session_id: host_id,
+                    raw: b"psst",
+                })
+            )
+            .await,
+            Err(ProcessError::InvalidOperation)
+        ));
+        send(
+            &app,
+            &first,
+            c2s::Message::SendToPlayer(ForwardMessage {
+                session_id: second_id,
+                raw: b"psst",
+            }),
+        )
+        .await
+        .unwrap();
+        send(&app, &first, c2s::Message::BroadcastToRoom { raw: b"gg" })
+            .await
+            .unwrap();
+
+        assert!(forwarded(&mut host_rx).is_empty());
+        assert!(forwarded(&mut guest_rx).is_empty());
+        assert_eq!(
+            vec![b"psst".to_vec(), b"gg".to_vec()],
+            forwarded(&mut second_rx)
+        );
+        assert!(forwarded(&mut first_rx).is_empty());
+    }
+
+    #[tokio::test]
+    async fn only_the_host_broadcasts_to_spectators() {
+        let app = Arc::new(RwLock::new(App::new()));
+        let (host, mut host_rx) = add_player(&mut *app.write().await).await;
+        let (guest, mut guest_rx) = add_player(&mut *app.write().await).await;
+        let (spectator, mut spectator_rx) = add_player(&mut *app.write().await).await;
+        let room = create_room(&app, &host, &[&guest]).await;
+
+        let code = room.read().await.get_code().to_string();
+        join_room(&app, &spectator, &code, true).await.unwrap();
+
+        assert!(matches!(
+            send(
+                &app,
+                &guest,
+                c2s::Message::BroadcastToSpectators { raw: b"score" }
+            )
+            .await,
+            Err(ProcessError::InvalidOperation)
+        ));
+        send(
+            &app,
+            &host,
+            c2s::Message::BroadcastToSpectators { raw: b"score" },
+        )
+        .await
+        .unwrap();
+
+        assert!(forwarded(&mut host_rx).is_empty());
+        assert!(forwarded(&mut guest_rx).is_empty());
+        assert_eq!(vec![b"score".to_vec()], forwarded(&mut spectator_rx));
+    }
+
+    #[tokio::test]
+    async fn topics_only_reach_subscribers() {
+        let app = Arc::new(RwLock::new(App::new()));
+        let (host, mut host_rx) = add_player(&mut *app.write().await).await;
+        let (guest, mut guest_rx) = add_player(&mut *app.write().await).await;
+        let (other, mut other_rx) = add_player(&mut *app.write().await).await;
+        let (first, mut first_rx) = add_player(&mut *app.write().await).await;
+        let (second, mut second_rx) = add_player(&mut *app.write().await).await;
+        let room = create_room(&app, &host, &[&guest, &other]).await;
+
+        let code = room.read().await.get_code().to_string();
+        for spectator in [&first, &second] {
+            join_room(&app, spectator, &code, true).await.unwrap();
+        }
+        for subscriber in [&host, &guest, &first, &second] {
+            send(&app, subscriber, c2s::Message::Subscribe { topic: b"chat" })
+                .await
+                .unwrap();
+        }
+
+        send(
+            &app,
+            &guest,
+            c2s::Message::PublishToTopic {
+                topic: b"chat",
+                raw: b"hi",
+            },
+        )
+        .await
+        .unwrap();
+        send(
+            &app,
+            &first,
+            c2s::Message::PublishToTopic {
+                topic: b"chat",
+                raw: b"gg",
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(vec![b"hi".to_vec()], forwarded(&mut host_rx));
+        assert!(forwarded(&mut guest_rx).is_empty());
+        assert!(forwarded(&mut other_rx).is_empty());
+        assert_eq!(vec![b"hi".to_vec()], forwarded(&mut first_rx));
+        assert_eq!(
+            vec![b"hi".to_vec(), b"gg".to_vec()],
+            forwarded(&mut second_rx)
+        );
+
+        send(&app, &host, c2s::Message::Unsubscribe { topic: b"chat" })
+            .await
+            .unwrap();
+        send(
+            &app,
+            &guest,
+            c2s::Message::PublishToTopic {
+                topic: b"chat",
+                raw: b"bye",
+            },
+        )
+        .await
+        .unwrap();
+        assert!(forwarded(&mut host_rx).is_empty());
+    }
+
+    #[tokio::test]
+    async fn multicast_delivers_past_failed_targets() {
+        let app = Arc::new(RwLock::new(App::new()));
+        let (host, _host_rx) = add_player(&mut *app.write().await).await;
+        let (gone, gone_rx) = add_player(&mut *app.write().await).await;
+        let (guest, mut guest_rx) = add_player(&mut *app.write().await).await;
+        create_room(&app, &host, &[&gone, &guest]).await;
+
+        let gone_id = session_id(&gone).await;
+        let guest_id = session_id(&guest).await;
+        let unknown_id = Uuid::new_v4();
+        drop(gone_rx);
+
+        assert!(matches!(
+            send(
+                &app,
+                &host,
+                c2s::Message::Multicast {
+                    targets: vec![&gone_id, &unknown_id, &guest_id],
+                    raw: b"hi",
+                }
+            )
+            .await,
+            Err(ProcessError::PlayerNotFound)
+        ));
+        assert_eq!(vec![b"hi".to_vec()], forwarded(&mut guest_rx));
     }
 }
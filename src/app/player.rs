@@ -1,25 +1,67 @@
-use std::sync::{Arc, Weak};
+use std::collections::{BTreeMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Instant;
 
 use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
 use crate::app::error::{ProcessError, SendError};
-use crate::app::Room;
+use crate::app::{Member, Room};
 use crate::proto::s2c;
+use crate::proto::{Capabilities, RequestId, ResumeToken};
+
+/// Maximum number of messages kept for a disconnected player, the oldest ones are dropped first.
+const MAX_PENDING_MESSAGES: usize = 256;
+
+/// Maximum number of entries in the data of a player.
+const MAX_DATA_ENTRIES: usize = 16;
+
+/// Maximum length of a player data value, in bytes.
+const MAX_DATA_VALUE_LEN: usize = 256;
+
+/// Custom data a player attaches to itself, such as its nickname.
+pub type PlayerData = BTreeMap<Vec<u8>, Vec<u8>>;
 
 pub struct Player {
     session_id: Uuid,
-    tx: UnboundedSender<Vec<u8>>,
+    resume_token: ResumeToken,
+    /// `None` while the player is disconnected.
+    tx: Option<UnboundedSender<Vec<u8>>>,
+    /// Messages sent while the player is disconnected, delivered once it resumes its session.
+    pending: Mutex<VecDeque<Vec<u8>>>,
+    /// Incremented every time the session is resumed by a new connection.
+    connection: u32,
+    disconnected_at: Option<Instant>,
+    remote_ip: Option<IpAddr>,
     room: Option<Weak<RwLock<Room>>>,
+    connected_at: Instant,
+    protocol_version: u16,
+    capabilities: Capabilities,
+    data: PlayerData,
 }
 
 impl Player {
-    pub fn new(tx: UnboundedSender<Vec<u8>>) -> Self {
+    pub fn new(
+        tx: UnboundedSender<Vec<u8>>,
+        remote_ip: Option<IpAddr>,
+        protocol_version: u16,
+        capabilities: Capabilities,
+    ) -> Self {
         Self {
             session_id: Uuid::new_v4(),
-            tx,
+            resume_token: rand::random(),
+            tx: Some(tx),
+            pending: Mutex::new(VecDeque::new()),
+            connection: 0,
+            disconnected_at: None,
+            remote_ip,
             room: None,
+            connected_at: Instant::now(),
+            protocol_version,
+            capabilities,
+            data: PlayerData::new(),
         }
     }
 
@@ -27,6 +69,56 @@ impl Player {
         &self.session_id
     }
 
+    pub fn get_resume_token(&self) -> &ResumeToken {
+        &self.resume_token
+    }
+
+    pub fn get_connection(&self) -> u32 {
+        self.connection
+    }
+
+    pub fn get_disconnected_at(&self) -> Option<&Instant> {
+        self.disconnected_at.as_ref()
+    }
+
+    pub fn get_remote_ip(&self) -> Option<&IpAddr> {
+        self.remote_ip.as_ref()
+    }
+
+    pub fn get_connected_at(&self) -> &Instant {
+        &self.connected_at
+    }
+
+    pub fn get_protocol_version(&self) -> u16 {
+        self.protocol_version
+    }
+
+    pub fn get_capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    pub fn get_data(&self) -> &PlayerData {
+        &self.data
+    }
+
+    /// Sets an entry of the player's data, or deletes it if `value` is empty.
+    pub fn set_data(&mut self, key: &[u8], value: &[u8]) -> Result<(), ProcessError> {
+        if value.is_empty() {
+            self.data.remove(key);
+            return Ok(());
+        }
+
+        if value.len() > MAX_DATA_VALUE_LEN {
+            return Err(ProcessError::PlayerDataTooLong);
+        }
+        if self.data.len() >= MAX_DATA_ENTRIES && !self.data.contains_key(key) {
+            return Err(ProcessError::TooManyPlayerDataEntries);
+        }
+
+        self.data.insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+
     pub fn get_room(&self) -> Option<Arc<RwLock<Room>>> {
         match &self.room {
             Some(w) => w.upgrade(),
@@ -38,36 +130,147 @@ impl Player {
         self.room.is_some()
     }
 
+    /// Sends a message to the player, unless it requires a capability the player lacks.
     pub async fn send(&self, msg: &s2c::Message<'_>) -> Result<(), SendError> {
+        if !self.capabilities.contains(msg.required_capabilities()) {
+            return Ok(());
+        }
+
         let mut buf = vec![];
         msg.encode(&mut buf).expect("TODO: panic message");
 
-        self.tx.send(buf)?;
+        match &self.tx {
+            Some(tx) => tx.send(buf)?,
+            None => {
+                let mut pending = self.pending.lock().unwrap();
+                if pending.len() == MAX_PENDING_MESSAGES {
+                    pending.pop_front();
+                }
+                pending.push_back(buf);
+            }
+        }
+
         Ok(())
     }
 
-    pub async fn enter_room(&mut self, room: &Arc<RwLock<Room>>) -> Result<(), ProcessError> {
+    /// Sends the messages telling the client the state of its connection, once it is established.
+    pub async fn send_handshake(&self) -> Result<(), SendError> {
+        self.send(&s2c::Message::Hello {
+            protocol_version: self.protocol_version,
+            capabilities: self.capabilities,
+        })
+        .await?;
+
+        let resume_token = self
+            .capabilities
+            .contains(Capabilities::SESSION_RESUME)
+            .then_some(&self.resume_token);
+
+        self.send(&s2c::Message::AssignSessionId {
+            session_id: &self.session_id,
+            resume_token,
+        })
+        .await
+    }
+
+    /// Detaches the player from its connection, buffering the messages sent to it from now on.
+    pub fn disconnect(&mut self) {
+        self.tx = None;
+        self.disconnected_at = Some(Instant::now());
+    }
+
+    /// Attaches the player to a new connection, and gives it a new resume token.
+    ///
+    /// The messages buffered in the meantime are delivered by [`Player::flush_pending`].
+    pub fn reconnect(
+        &mut self,
+        tx: UnboundedSender<Vec<u8>>,
+        remote_ip: Option<IpAddr>,
+        protocol_version: u16,
+        capabilities: Capabilities,
+    ) {
+        self.tx = Some(tx);
+        self.remote_ip = remote_ip;
+        self.connection = self.connection.wrapping_add(1);
+        self.disconnected_at = None;
+        self.resume_token = rand::random();
+        self.protocol_version = protocol_version;
+        self.capabilities = capabilities;
+    }
+
+    pub fn flush_pending(&self) -> Result<(), SendError> {
+        let tx = match &self.tx {
+            Some(tx) => tx,
+            None => return Ok(()),
+        };
+
+        for buf in self.pending.lock().unwrap().drain(..) {
+            tx.send(buf)?;
+        }
+
+        Ok(())
+    }
+
+    /// Makes the player a member of a room, and sends it the room's state.
+    ///
+    /// `room_state` is the locked `room`, and `members` a snapshot of its members taken with
+    /// [`Room::get_members`] before locking the player.
+    pub async fn enter_room(
+        &mut self,
+        room: &Arc<RwLock<Room>>,
+        room_state: &Room,
+        members: &[Member],
+        request_id: Option<RequestId>,
+    ) -> Result<(), ProcessError> {
         if self.is_in_room() {
             return Err(ProcessError::InvalidOperation);
         }
 
         self.set_room_unchecked(room);
 
+        let room = room_state;
+        let with_data = self.capabilities.contains(Capabilities::PLAYER_DATA);
+        let with_role = self.capabilities.contains(Capabilities::SPECTATORS);
+        let members = self.capabilities.contains(Capabilities::ROSTER).then(|| {
+            members
+                .iter()
+                .map(|member| s2c::MemberInfo {
+                    session_id: &member.session_id,
+                    data: with_data.then(|| data_entries(&member.data)),
+                    role: with_role.then_some(member.role),
+                })
+                .collect()
+        });
+
         self.send(&s2c::Message::RoomJoined {
-            host_session_id: &room.read().await.get_host().read().await.get_session_id(),
+            host_session_id: room.get_host_session_id(),
+            members,
+            request_id,
         })
         .await?;
 
+        room.send_state(self).await?;
+
         println!(
             "Player {} joined room {}",
             self.get_session_id(),
-            room.read().await.get_code()
+            room.get_code()
         );
 
         Ok(())
     }
 
+    pub fn leave_room(&mut self) {
+        self.room = None;
+    }
+
     pub(crate) fn set_room_unchecked(&mut self, room: &Arc<RwLock<Room>>) {
         self.room = Some(Arc::downgrade(room));
     }
 }
+
+pub(crate) fn data_entries(data: &PlayerData) -> Vec<s2c::PlayerDataEntry<'_>> {
+    data.iter()
+        .map(|(key, value)| s2c::PlayerDataEntry { key, value })
+        .collect()
+}
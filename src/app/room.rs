@@ -1,56 +1,1038 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::net::IpAddr;
 use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
-use crate::app::Player;
+use crate::app::error::{ProcessError, SendError};
+use crate::app::player::data_entries;
+use crate::app::{Config, Player, PlayerData};
 use crate::code::Code;
-use crate::proto::s2c;
+use crate::proto::c2s::{HostMigration, RoomFilter, RoomOptions};
+use crate::proto::s2c::{self, MemberRole, RoomPhase};
+use crate::proto::{Capabilities, ForwardMessage};
+
+/// Maximum number of values held by a room.
+const MAX_VALUES: usize = 64;
+
+/// Maximum length of a room value, in bytes.
+const MAX_VALUE_LEN: usize = 1024;
+
+/// Maximum number of topics a member may be subscribed to.
+const MAX_SUBSCRIPTIONS: usize = 32;
+
+/// Member of a room, as snapshot by [`Room::get_members`].
+pub struct Member {
+    pub session_id: Uuid,
+    pub role: MemberRole,
+    pub data: PlayerData,
+}
+
+/// Payload broadcast to a room, kept in its history.
+struct HistoryEntry {
+    session_id: Uuid,
+    raw: Vec<u8>,
+    at: Instant,
+}
 
 pub struct Room {
     code: Code,
-    host: Weak<RwLock<Player>>,
+    host: Uuid,
+    host_migration: HostMigration,
+    /// `0` for no limit.
+    max_players: u8,
+    password: Option<Vec<u8>>,
+    public: bool,
+    metadata: Vec<u8>,
     players: HashMap<Uuid, Weak<RwLock<Player>>>,
+    /// Members who joined as spectators, among `players`.
+    spectators: HashSet<Uuid>,
+    /// Banned session ids, with the IP address banned along with them.
+    bans: HashMap<Uuid, Option<IpAddr>>,
+    /// Shared state of the room, sent to every member.
+    values: BTreeMap<Vec<u8>, Vec<u8>>,
+    host_only_values: bool,
+    phase: RoomPhase,
+    /// Members ready for the game to start.
+    ready: HashSet<Uuid>,
+    /// When the game begins, while the room is starting.
+    start_at: Option<SystemTime>,
+    /// Last payloads broadcast by players, replayed to the players joining the room.
+    history: VecDeque<HistoryEntry>,
+    /// Total size of the payloads in `history`.
+    history_bytes: usize,
+    /// `0` if the history is disabled.
+    history_len: usize,
+    history_max_bytes: usize,
+    history_max_age: Option<Duration>,
+    /// Members subscribed to each topic.
+    topics: HashMap<Vec<u8>, HashSet<Uuid>>,
+    /// Set once the room is removed, as players may still be joining it.
+    closed: bool,
 }
 
 impl Room {
-    pub async fn new(host: &Arc<RwLock<Player>>) -> Self {
+    /// Creates a room, whose history is kept within the limits of `config`.
+    pub async fn new(
+        code: Code,
+        host: &Arc<RwLock<Player>>,
+        options: &RoomOptions<'_>,
+        config: &Config,
+    ) -> Self {
         let host_id = *host.read().await.get_session_id();
-        let host = Arc::downgrade(host);
+
+        let history_max_bytes = match options.history_max_bytes as usize {
+            0 => config.max_history_bytes,
+            max_bytes => max_bytes.min(config.max_history_bytes),
+        };
 
         Self {
-            code: Code::new(),
-            host: host.clone(),
-            players: HashMap::from([(host_id, host)]),
+            code,
+            host: host_id,
+            host_migration: options.host_migration,
+            max_players: options.max_players,
+            password: options
+                .password
+                .filter(|p| !p.is_empty())
+                .map(<[u8]>::to_vec),
+            public: options.public,
+            metadata: options.metadata.to_vec(),
+            players: HashMap::from([(host_id, Arc::downgrade(host))]),
+            spectators: HashSet::new(),
+            bans: HashMap::new(),
+            values: BTreeMap::new(),
+            host_only_values: options.host_only_values,
+            phase: RoomPhase::Lobby,
+            ready: HashSet::new(),
+            start_at: None,
+            history: VecDeque::new(),
+            history_bytes: 0,
+            history_len: (options.history_len as usize).min(config.max_history_len),
+            history_max_bytes,
+            history_max_age: (options.history_max_age != 0)
+                .then(|| Duration::from_secs(options.history_max_age as u64)),
+            topics: HashMap::new(),
+            closed: false,
         }
     }
 
-    pub async fn add_player(&mut self, player: &Arc<RwLock<Player>>) {
-        let session_id = *player.read().await.get_session_id();
+    pub async fn add_player(
+        &mut self,
+        player: &Arc<RwLock<Player>>,
+        password: Option<&[u8]>,
+        role: MemberRole,
+    ) -> Result<(), ProcessError> {
+        if self.closed {
+            return Err(ProcessError::RoomNotFound);
+        }
+        if !self.check_password(password) {
+            return Err(ProcessError::WrongPassword);
+        }
+
+        let (session_id, data) = {
+            let player = player.read().await;
+            if self.is_banned(player.get_session_id(), player.get_remote_ip()) {
+                return Err(ProcessError::Banned);
+            }
+
+            (*player.get_session_id(), player.get_data().clone())
+        };
+
+        if role == MemberRole::Player && self.is_full() {
+            return Err(ProcessError::RoomFull);
+        }
+
         self.players.insert(session_id, Arc::downgrade(player));
+        if role == MemberRole::Spectator {
+            self.spectators.insert(session_id);
+        }
 
-        self.host
-            .upgrade()
-            .unwrap()
-            .read()
-            .await
-            .send(&s2c::Message::PlayerJoined {
+        let entries = data_entries(&data);
+        for member in self.get_players() {
+            let member = member.read().await;
+            if member.get_session_id() == &session_id {
+                continue;
+            }
+
+            // Only the members able to decode them receive the new player's data and role
+            let capabilities = member.get_capabilities();
+            let msg = s2c::Message::PlayerJoined {
                 player_session_id: &session_id,
-            })
-            .await
-            .unwrap();
+                data: capabilities
+                    .contains(Capabilities::PLAYER_DATA)
+                    .then(|| entries.clone()),
+                role: capabilities
+                    .contains(Capabilities::SPECTATORS)
+                    .then_some(role),
+            };
+            if let Err(e) = member.send(&msg).await {
+                eprintln!("broadcast error(room={}): {:?}", self.code, e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Adds a player without any check nor notification, for rooms formed by the matchmaker.
+    pub(crate) async fn add_player_unchecked(&mut self, player: &Arc<RwLock<Player>>) {
+        let session_id = *player.read().await.get_session_id();
+        self.players.insert(session_id, Arc::downgrade(player));
+    }
+
+    pub async fn remove_player(&mut self, session_id: &Uuid) -> Option<Weak<RwLock<Player>>> {
+        let player = self.players.remove(session_id)?;
+        self.spectators.remove(session_id);
+        self.ready.remove(session_id);
+        self.topics.retain(|_, subscribers| {
+            subscribers.remove(session_id);
+            !subscribers.is_empty()
+        });
+
+        self.broadcast(&s2c::Message::PlayerLeft {
+            player_session_id: session_id,
+        })
+        .await;
+
+        Some(player)
+    }
+
+    /// Removes all the players from the room, returning the ones still connected, and closes it.
+    pub fn drain_players(&mut self) -> Vec<Arc<RwLock<Player>>> {
+        self.closed = true;
+        self.spectators.clear();
+        self.topics.clear();
+        self.players
+            .drain()
+            .filter_map(|(_, w)| w.upgrade())
+            .collect()
+    }
+
+    pub async fn broadcast(&self, msg: &s2c::Message<'_>) {
+        for player in self.get_players() {
+            if let Err(e) = player.read().await.send(msg).await {
+                eprintln!("broadcast error(room={}): {:?}", self.code, e);
+            }
+        }
+    }
+
+    /// Sends a message to every member of the room, except one.
+    pub async fn broadcast_except(&self, msg: &s2c::Message<'_>, except: &Uuid) {
+        for (session_id, player) in &self.players {
+            if session_id == except {
+                continue;
+            }
+
+            if let Some(player) = player.upgrade() {
+                if let Err(e) = player.read().await.send(msg).await {
+                    eprintln!("broadcast error(room={}): {:?}", self.code, e);
+                }
+            }
+        }
     }
 
     pub fn get_code(&self) -> &Code {
         &self.code
     }
 
-    pub fn get_host(&self) -> Arc<RwLock<Player>> {
-        self.host.upgrade().unwrap()
+    /// Number of members, spectators excluded.
+    pub fn get_player_count(&self) -> usize {
+        self.players.len() - self.spectators.len()
+    }
+
+    pub fn get_role(&self, session_id: &Uuid) -> MemberRole {
+        if self.spectators.contains(session_id) {
+            MemberRole::Spectator
+        } else {
+            MemberRole::Player
+        }
+    }
+
+    pub fn is_spectator(&self, session_id: &Uuid) -> bool {
+        self.spectators.contains(session_id)
+    }
+
+    pub fn get_max_players(&self) -> u8 {
+        self.max_players
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.max_players != 0 && self.get_player_count() >= self.max_players as usize
+    }
+
+    pub fn is_public(&self) -> bool {
+        self.public
+    }
+
+    pub fn get_metadata(&self) -> &[u8] {
+        &self.metadata
+    }
+
+    /// Whether players may join the room without being given its code: it is not full, has no
+    /// password, and its game has not started.
+    pub fn is_open(&self) -> bool {
+        !self.is_full() && !self.has_password() && self.phase == RoomPhase::Lobby
+    }
+
+    /// Whether the room is listed for a filter.
+    pub fn matches(&self, filter: &RoomFilter<'_>) -> bool {
+        self.public
+            && (filter.include_full || !self.is_full())
+            && self.metadata.starts_with(filter.metadata_prefix)
+    }
+
+    pub fn get_info(&self) -> s2c::RoomInfo<'_> {
+        s2c::RoomInfo {
+            code: self.code,
+            player_count: self.get_player_count().try_into().unwrap_or(u16::MAX),
+            max_players: self.max_players,
+            has_password: self.has_password(),
+            metadata: &self.metadata,
+        }
+    }
+
+    pub fn has_password(&self) -> bool {
+        self.password.is_some()
+    }
+
+    fn check_password(&self, password: Option<&[u8]>) -> bool {
+        let expected = match &self.password {
+            Some(expected) => expected,
+            None => return true,
+        };
+        let password = password.unwrap_or_default();
+
+        // Compare in constant time, so the password can't be guessed from response times
+        expected.len() == password.len()
+            && expected
+                .iter()
+                .zip(password)
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0
+    }
+
+    pub fn ban(&mut self, session_id: &Uuid, ip: Option<IpAddr>) {
+        self.bans.insert(*session_id, ip);
+    }
+
+    pub fn unban(&mut self, session_id: &Uuid) -> Result<(), ProcessError> {
+        match self.bans.remove(session_id) {
+            Some(_) => Ok(()),
+            None => Err(ProcessError::PlayerNotFound),
+        }
+    }
+
+    pub fn is_banned(&self, session_id: &Uuid, ip: Option<&IpAddr>) -> bool {
+        self.bans.contains_key(session_id)
+            || ip.is_some_and(|ip| self.bans.values().any(|banned| banned.as_ref() == Some(ip)))
+    }
+
+    pub fn get_values(&self) -> impl Iterator<Item = (&[u8], &[u8])> {
+        self.values
+            .iter()
+            .map(|(k, v)| (k.as_slice(), v.as_slice()))
+    }
+
+    /// Whether a member may set the values of the room.
+    pub fn can_set_values(&self, session_id: &Uuid) -> bool {
+        !self.is_spectator(session_id) && (!self.host_only_values || self.is_host(session_id))
+    }
+
+    /// Sets a value of the room, and notifies every member.
+    pub async fn set_value(&mut self, key: &[u8], value: &[u8]) -> Result<(), ProcessError> {
+        if value.len() > MAX_VALUE_LEN {
+            return Err(ProcessError::RoomValueTooLong);
+        }
+        if self.values.len() >= MAX_VALUES && !self.values.contains_key(key) {
+            return Err(ProcessError::TooManyRoomValues);
+        }
+
+        self.values.insert(key.to_vec(), value.to_vec());
+
+        self.broadcast(&s2c::Message::RoomValueChanged {
+            key,
+            value: Some(value),
+        })
+        .await;
+
+        Ok(())
+    }
+
+    /// Deletes a value of the room, and notifies every member if it was set.
+    pub async fn delete_value(&mut self, key: &[u8]) {
+        if self.values.remove(key).is_some() {
+            self.broadcast(&s2c::Message::RoomValueChanged { key, value: None })
+                .await;
+        }
+    }
+
+    /// Sends the state of the room to a player joining it.
+    pub async fn send_state(&self, player: &Player) -> Result<(), SendError> {
+        for (key, value) in self.get_values() {
+            player
+                .send(&s2c::Message::RoomValueChanged {
+                    key,
+                    value: Some(value),
+                })
+                .await?;
+        }
+
+        match (self.phase, self.start_at) {
+            (RoomPhase::Lobby, _) => {}
+            (RoomPhase::Starting, Some(start_at)) => {
+                player
+                    .send(&s2c::Message::GameStarting {
+                        start_at: unix_millis(start_at),
+                    })
+                    .await?
+            }
+            (phase, _) => {
+                player
+                    .send(&s2c::Message::RoomPhaseChanged { phase })
+                    .await?
+            }
+        }
+
+        for player_session_id in &self.ready {
+            player
+                .send(&s2c::Message::PlayerReadyChanged {
+                    player_session_id,
+                    ready: true,
+                })
+                .await?;
+        }
+
+        let now = Instant::now();
+        for entry in &self.history {
+            if self.is_expired(entry, now) {
+                continue;
+            }
+
+            player
+                .send(&s2c::Message::HistoryFromPlayer(ForwardMessage {
+                    session_id: entry.session_id,
+                    raw: &entry.raw,
+                }))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Keeps a payload broadcast by a member in the history of the room, if it is enabled.
+    ///
+    /// The oldest payloads are dropped once the history exceeds one of its limits. A payload
+    /// larger than the size limit on its own is not kept.
+    pub fn record_history(&mut self, session_id: &Uuid, raw: &[u8]) {
+        if self.history_len == 0 || raw.len() > self.history_max_bytes {
+            return;
+        }
+
+        let now = Instant::now();
+        self.history.push_back(HistoryEntry {
+            session_id: *session_id,
+            raw: raw.to_vec(),
+            at: now,
+        });
+        self.history_bytes += raw.len();
+
+        while let Some(oldest) = self.history.front() {
+            let over_limit = self.history.len() > self.history_len
+                || self.history_bytes > self.history_max_bytes
+                || self.is_expired(oldest, now);
+            if !over_limit {
+                break;
+            }
+
+            self.history_bytes -= oldest.raw.len();
+            self.history.pop_front();
+        }
+    }
+
+    fn is_expired(&self, entry: &HistoryEntry, now: Instant) -> bool {
+        self.history_max_age
+            .is_some_and(|max_age| now.duration_since(entry.at) > max_age)
+    }
+
+    /// Subscribes a member to a topic. Subscribing twice to the same topic has no effect.
+    pub fn subscribe(&mut self, session_id: &Uuid, topic: &[u8]) -> Result<(), ProcessError> {
+        if self
+            .topics
+            .get(topic)
+            .is_some_and(|subscribers| subscribers.contains(session_id))
+        {
+            return Ok(());
+        }
+
+        let subscriptions = self
+            .topics
+            .values()
+            .filter(|subscribers| subscribers.contains(session_id))
+            .count();
+        if subscriptions >= MAX_SUBSCRIPTIONS {
+            return Err(ProcessError::TooManySubscriptions);
+        }
+
+        self.topics
+            .entry(topic.to_vec())
+            .or_default()
+            .insert(*session_id);
+        Ok(())
+    }
+
+    pub fn unsubscribe(&mut self, session_id: &Uuid, topic: &[u8]) {
+        if let Some(subscribers) = self.topics.get_mut(topic) {
+            subscribers.remove(session_id);
+            if subscribers.is_empty() {
+                self.topics.remove(topic);
+            }
+        }
+    }
+
+    /// Members subscribed to a topic, with their session id.
+    pub fn get_subscribers<'a>(
+        &'a self,
+        topic: &[u8],
+    ) -> impl Iterator<Item = (&'a Uuid, Arc<RwLock<Player>>)> + 'a {
+        self.topics
+            .get(topic)
+            .into_iter()
+            .flatten()
+            .filter_map(|session_id| Some((session_id, self.get_player(session_id)?)))
+    }
+
+    pub fn get_phase(&self) -> RoomPhase {
+        self.phase
+    }
+
+    pub fn is_ready(&self, session_id: &Uuid) -> bool {
+        self.ready.contains(session_id)
+    }
+
+    /// Marks a member as ready for the game to start or not, and notifies every member.
+    pub async fn set_ready(&mut self, session_id: &Uuid, ready: bool) -> Result<(), ProcessError> {
+        if !matches!(self.phase, RoomPhase::Lobby | RoomPhase::Finished)
+            || self.is_spectator(session_id)
+        {
+            return Err(ProcessError::InvalidOperation);
+        }
+
+        let changed = if ready {
+            self.ready.insert(*session_id)
+        } else {
+            self.ready.remove(session_id)
+        };
+
+        if changed {
+            self.broadcast(&s2c::Message::PlayerReadyChanged {
+                player_session_id: session_id,
+                ready,
+            })
+            .await;
+        }
+
+        Ok(())
+    }
+
+    /// Starts the countdown to the game if every member is ready, and notifies every member.
+    ///
+    /// The ready flags are cleared, and the time the game begins at is returned, for
+    /// [`Room::begin_game`].
+    pub async fn start_game(&mut self, delay: Duration) -> Result<SystemTime, ProcessError> {
+        if !matches!(self.phase, RoomPhase::Lobby | RoomPhase::Finished) {
+            return Err(ProcessError::InvalidOperation);
+        }
+        if !self
+            .players
+            .keys()
+            .all(|id| self.ready.contains(id) || self.is_spectator(id))
+        {
+            return Err(ProcessError::NotAllReady);
+        }
+
+        let start_at = SystemTime::now() + delay;
+        self.phase = RoomPhase::Starting;
+        self.start_at = Some(start_at);
+        self.ready.clear();
+
+        self.broadcast(&s2c::Message::GameStarting {
+            start_at: unix_millis(start_at),
+        })
+        .await;
+
+        println!("Room {} starting", self.code);
+
+        Ok(start_at)
+    }
+
+    /// Ends the countdown of the game beginning at `start_at`, unless it was ended early.
+    pub async fn begin_game(&mut self, start_at: SystemTime) {
+        if self.phase != RoomPhase::Starting || self.start_at != Some(start_at) {
+            return;
+        }
+
+        self.set_phase(RoomPhase::InGame).await;
+    }
+
+    /// Ends the game, starting or in progress.
+    pub async fn end_game(&mut self) -> Result<(), ProcessError> {
+        if !matches!(self.phase, RoomPhase::Starting | RoomPhase::InGame) {
+            return Err(ProcessError::InvalidOperation);
+        }
+
+        self.set_phase(RoomPhase::Finished).await;
+
+        Ok(())
+    }
+
+    async fn set_phase(&mut self, phase: RoomPhase) {
+        self.phase = phase;
+        self.start_at = None;
+
+        self.broadcast(&s2c::Message::RoomPhaseChanged { phase })
+            .await;
+
+        println!("Room {} is now {:?}", self.code, phase);
+    }
+
+    pub fn get_host(&self) -> Option<Arc<RwLock<Player>>> {
+        self.get_player(&self.host)
+    }
+
+    pub fn get_host_session_id(&self) -> &Uuid {
+        &self.host
+    }
+
+    pub fn is_host(&self, session_id: &Uuid) -> bool {
+        &self.host == session_id
+    }
+
+    /// Makes a member of the room its host, and notifies every member.
+    pub async fn set_host(&mut self, session_id: &Uuid) -> Result<(), ProcessError> {
+        if self.get_player(session_id).is_none() {
+            return Err(ProcessError::PlayerNotFound);
+        }
+        if self.is_spectator(session_id) {
+            return Err(ProcessError::InvalidOperation);
+        }
+
+        self.host = *session_id;
+
+        self.broadcast(&s2c::Message::HostChanged {
+            host_session_id: session_id,
+        })
+        .await;
+
+        println!("Player {} is now host of room {}", session_id, self.code);
+
+        Ok(())
+    }
+
+    /// Chooses the member who should replace the current host, according to the room's host
+    /// migration policy. Members waiting to resume their session are only chosen when no
+    /// connected member is left.
+    ///
+    /// Returns `None` if the room should be closed instead.
+    pub async fn next_host(&self) -> Option<Uuid> {
+        match self.host_migration {
+            HostMigration::Disabled => None,
+            HostMigration::LongestConnected => {
+                let mut next_host = None;
+
+                for player in self.get_players() {
+                    let player = player.read().await;
+                    if self.is_host(player.get_session_id())
+                        || self.is_spectator(player.get_session_id())
+                    {
+                        continue;
+                    }
+
+                    let rank = (
+                        player.get_disconnected_at().is_some(),
+                        *player.get_connected_at(),
+                    );
+                    match next_host {
+                        Some((_, best)) if best <= rank => {}
+                        _ => next_host = Some((*player.get_session_id(), rank)),
+                    }
+                }
+
+                next_host.map(|(session_id, _)| session_id)
+            }
+        }
     }
 
     pub fn get_player(&self, session_id: &Uuid) -> Option<Arc<RwLock<Player>>> {
         self.players.get(session_id).and_then(|w| w.upgrade())
     }
+
+    /// Snapshot of the members of the room, with their role and data.
+    pub async fn get_members(&self) -> Vec<Member> {
+        let mut members = Vec::with_capacity(self.players.len());
+
+        for player in self.get_players() {
+            let player = player.read().await;
+            members.push(Member {
+                session_id: *player.get_session_id(),
+                role: self.get_role(player.get_session_id()),
+                data: player.get_data().clone(),
+            });
+        }
+
+        members
+    }
+
+    pub fn get_spectators(&self) -> impl Iterator<Item = Arc<RwLock<Player>>> + '_ {
+        self.spectators
+            .iter()
+            .filter_map(|session_id| self.get_player(session_id))
+    }
+
+    pub fn get_players(&self) -> impl Iterator<Item = Arc<RwLock<Player>>> + '_ {
+        self.players.values().filter_map(|w| w.upgrade())
+    }
+}
+
+/// Milliseconds since the Unix epoch, as sent to clients.
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::testing::{connect, session_id};
+    use crate::code::CodeFormat;
+
+    async fn new_room(host: &Arc<RwLock<Player>>, options: &RoomOptions<'_>) -> Room {
+        Room::new(
+            CodeFormat::default().generate(),
+            host,
+            options,
+            &Config::default(),
+        )
+        .await
+    }
+
+    /// Payloads replayed from the history of a room to a player joining it.
+    async fn replay_history(room: &Room) -> Vec<Vec<u8>> {
+        let (player, mut rx) = connect(None);
+        room.send_state(&*player.read().await).await.unwrap();
+
+        std::iter::from_fn(|| rx.try_recv().ok())
+            .filter_map(|buf| match s2c::Message::decode(&buf) {
+                Ok(s2c::Message::HistoryFromPlayer(fwd)) => Some(fwd.raw.to_vec()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn history_keeps_the_last_payloads() {
+        let (host, _host_rx) = connect(None);
+        let host_id = session_id(&host).await;
+
+        let room = new_room(&host, &RoomOptions::default()).await;
+        assert!(replay_history(&room).await.is_empty());
+
+        let options = RoomOptions {
+            history_len: 3,
+            history_max_bytes: 4,
+            ..RoomOptions::default()
+        };
+        let mut room = new_room(&host, &options).await;
+        for raw in [&b"a"[..], b"bb", b"toolong", b"c", b"d"] {
+            room.record_history(&host_id, raw);
+        }
+
+        assert_eq!(
+            vec![b"bb".to_vec(), b"c".to_vec(), b"d".to_vec()],
+            replay_history(&room).await
+        );
+    }
+
+    #[tokio::test]
+    async fn history_is_capped_by_the_server() {
+        let (host, _host_rx) = connect(None);
+        let host_id = session_id(&host).await;
+
+        let config = Config {
+            max_history_len: 2,
+            max_history_bytes: 4,
+            ..Config::default()
+        };
+        let options = RoomOptions {
+            history_len: u16::MAX,
+            history_max_bytes: 0,
+            ..RoomOptions::default()
+        };
+        let mut room = Room::new(CodeFormat::default().generate(), &host, &options, &config).await;
+        for raw in [&b"aaa"[..], b"bb", b"toolong", b"c", b"d"] {
+            room.record_history(&host_id, raw);
+        }
+
+        assert_eq!(
+            vec![b"c".to_vec(), b"d".to_vec()],
+            replay_history(&room).await
+        );
+    }
+
+    #[tokio::test]
+    async fn next_host_follows_the_migration_policy() {
+        let (host, _host_rx) = connect(None);
+        let (first, _first_rx) = connect(None);
+        let (second, _second_rx) = connect(None);
+        let (spectator, _spectator_rx) = connect(None);
+
+        let mut room = new_room(&host, &RoomOptions::default()).await;
+        room.add_player(&first, None, MemberRole::Player)
+            .await
+            .unwrap();
+        assert_eq!(None, room.next_host().await);
+
+        let options = RoomOptions {
+            host_migration: HostMigration::LongestConnected,
+            ..RoomOptions::default()
+        };
+        let mut room = new_room(&host, &options).await;
+        room.add_player(&spectator, None, MemberRole::Spectator)
+            .await
+            .unwrap();
+        assert_eq!(None, room.next_host().await);
+
+        room.add_player(&second, None, MemberRole::Player)
+            .await
+            .unwrap();
+        room.add_player(&first, None, MemberRole::Player)
+            .await
+            .unwrap();
+        assert_eq!(Some(session_id(&first).await), room.next_host().await);
+    }
+
+    #[tokio::test]
+    async fn next_host_prefers_connected_members() {
+        let (host, _host_rx) = connect(None);
+        let (offline, _offline_rx) = connect(None);
+        let (online, _online_rx) = connect(None);
+
+        let options = RoomOptions {
+            host_migration: HostMigration::LongestConnected,
+            ..RoomOptions::default()
+        };
+        let mut room = new_room(&host, &options).await;
+        room.add_player(&offline, None, MemberRole::Player)
+            .await
+            .unwrap();
+        offline.write().await.disconnect();
+        assert_eq!(Some(session_id(&offline).await), room.next_host().await);
+
+        room.add_player(&online, None, MemberRole::Player)
+            .await
+            .unwrap();
+        assert_eq!(Some(session_id(&online).await), room.next_host().await);
+    }
+
+    #[tokio::test]
+    async fn set_host_only_accepts_players() {
+        let (host, _host_rx) = connect(None);
+        let (guest, _guest_rx) = connect(None);
+        let (spectator, _spectator_rx) = connect(None);
+        let (stranger, _stranger_rx) = connect(None);
+
+        let mut room = new_room(&host, &RoomOptions::default()).await;
+        room.add_player(&guest, None, MemberRole::Player)
+            .await
+            .unwrap();
+        room.add_player(&spectator, None, MemberRole::Spectator)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            room.set_host(&session_id(&stranger).await).await,
+            Err(ProcessError::PlayerNotFound)
+        ));
+        assert!(matches!(
+            room.set_host(&session_id(&spectator).await).await,
+            Err(ProcessError::InvalidOperation)
+        ));
+
+        let guest_id = session_id(&guest).await;
+        room.set_host(&guest_id).await.unwrap();
+        assert!(room.is_host(&guest_id));
+        assert!(Arc::ptr_eq(&guest, &room.get_host().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn bans_reject_the_session_and_its_ip() {
+        let ip: IpAddr = [192, 0, 2, 1].into();
+        let (host, _host_rx) = connect(None);
+        let (banned, _banned_rx) = connect(Some(ip));
+        let (same_ip, _same_ip_rx) = connect(Some(ip));
+        let (other_ip, _other_ip_rx) = connect(Some([192, 0, 2, 2].into()));
+
+        let mut room = new_room(&host, &RoomOptions::default()).await;
+        let banned_id = session_id(&banned).await;
+        room.ban(&banned_id, Some(ip));
+
+        for player in [&banned, &same_ip] {
+            assert!(matches!(
+                room.add_player(player, None, MemberRole::Spectator).await,
+                Err(ProcessError::Banned)
+            ));
+        }
+        room.add_player(&other_ip, None, MemberRole::Player)
+            .await
+            .unwrap();
+
+        room.unban(&banned_id).unwrap();
+        assert!(matches!(
+            room.unban(&banned_id),
+            Err(ProcessError::PlayerNotFound)
+        ));
+        room.add_player(&banned, None, MemberRole::Player)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn start_game_requires_every_member_ready() {
+        let (host, _host_rx) = connect(None);
+        let (guest, _guest_rx) = connect(None);
+        let host_id = session_id(&host).await;
+        let guest_id = session_id(&guest).await;
+
+        let mut room = new_room(&host, &RoomOptions::default()).await;
+        room.add_player(&guest, None, MemberRole::Player)
+            .await
+            .unwrap();
+
+        room.set_ready(&host_id, true).await.unwrap();
+        assert!(matches!(
+            room.start_game(Duration::ZERO).await,
+            Err(ProcessError::NotAllReady)
+        ));
+
+        room.set_ready(&guest_id, true).await.unwrap();
+        let start_at = room.start_game(Duration::ZERO).await.unwrap();
+        assert_eq!(RoomPhase::Starting, room.get_phase());
+        assert!(!room.is_ready(&host_id));
+
+        room.begin_game(start_at).await;
+        assert_eq!(RoomPhase::InGame, room.get_phase());
+    }
+
+    #[tokio::test]
+    async fn spectators_do_not_count_toward_capacity() {
+        let (host, _host_rx) = connect(None);
+        let (spectator, _spectator_rx) = connect(None);
+        let (guest, _guest_rx) = connect(None);
+
+        let options = RoomOptions {
+            max_players: 1,
+            ..RoomOptions::default()
+        };
+        let mut room = new_room(&host, &options).await;
+
+        room.add_player(&spectator, None, MemberRole::Spectator)
+            .await
+            .unwrap();
+        assert!(matches!(
+            room.add_player(&guest, None, MemberRole::Player).await,
+            Err(ProcessError::RoomFull)
+        ));
+        assert_eq!(1, room.get_player_count());
+
+        // Only the players have to be ready
+        room.set_ready(&session_id(&host).await, true)
+            .await
+            .unwrap();
+        room.start_game(Duration::ZERO).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn passwords_gate_joining() {
+        let (host, _host_rx) = connect(None);
+        let (guest, _guest_rx) = connect(None);
+
+        let options = RoomOptions {
+            password: Some(b"secret"),
+            ..RoomOptions::default()
+        };
+        let mut room = new_room(&host, &options).await;
+        assert!(!room.is_open());
+
+        for password in [None, Some(&b"wrong"[..]), Some(b"secre"), Some(b"")] {
+            assert!(matches!(
+                room.add_player(&guest, password, MemberRole::Player).await,
+                Err(ProcessError::WrongPassword)
+            ));
+        }
+        assert_eq!(1, room.get_player_count());
+
+        room.add_player(&guest, Some(b"secret"), MemberRole::Player)
+            .await
+            .unwrap();
+        assert_eq!(2, room.get_player_count());
+
+        // An empty password is the same as none, and any password is accepted
+        let options = RoomOptions {
+            password: Some(b""),
+            ..RoomOptions::default()
+        };
+        let mut room = new_room(&host, &options).await;
+        assert!(!room.has_password());
+        assert!(room.is_open());
+        room.add_player(&guest, Some(b"whatever"), MemberRole::Player)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn removed_rooms_reject_players() {
+        let (host, _host_rx) = connect(None);
+        let (guest, _guest_rx) = connect(None);
+
+        let mut room = new_room(&host, &RoomOptions::default()).await;
+        assert_eq!(1, room.drain_players().len());
+
+        assert!(matches!(
+            room.add_player(&guest, None, MemberRole::Player).await,
+            Err(ProcessError::RoomNotFound)
+        ));
+        assert_eq!(0, room.get_player_count());
+    }
+
+    #[tokio::test]
+    async fn subscriptions_are_bounded_and_dropped_with_members() {
+        let (host, _host_rx) = connect(None);
+        let (guest, _guest_rx) = connect(None);
+        let host_id = session_id(&host).await;
+        let guest_id = session_id(&guest).await;
+
+        let mut room = new_room(&host, &RoomOptions::default()).await;
+        room.add_player(&guest, None, MemberRole::Player)
+            .await
+            .unwrap();
+
+        room.subscribe(&host_id, b"chat").unwrap();
+        room.subscribe(&guest_id, b"chat").unwrap();
+        room.subscribe(&guest_id, b"voice").unwrap();
+        assert_eq!(2, room.get_subscribers(b"chat").count());
+        assert_eq!(0, room.get_subscribers(b"world").count());
+
+        room.unsubscribe(&host_id, b"chat");
+        room.remove_player(&guest_id).await;
+        assert_eq!(0, room.get_subscribers(b"chat").count());
+        assert_eq!(0, room.get_subscribers(b"voice").count());
+
+        for topic in 0..MAX_SUBSCRIPTIONS as u8 {
+            room.subscribe(&host_id, &[topic]).unwrap();
+        }
+        assert!(matches!(
+            room.subscribe(&host_id, b"chat"),
+            Err(ProcessError::TooManySubscriptions)
+        ));
+    }
 }
@@ -0,0 +1,37 @@
+//! Helpers shared by the tests of the app.
+
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::app::{App, Player};
+use crate::proto::Capabilities;
+
+/// Creates a player with every capability, along with the receiving end of its connection, which
+/// must be kept alive for messages to be sent to the player.
+pub(crate) fn connect(
+    remote_ip: Option<IpAddr>,
+) -> (Arc<RwLock<Player>>, UnboundedReceiver<Vec<u8>>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let player = Player::new(tx, remote_ip, 1, Capabilities::ALL);
+
+    (Arc::new(RwLock::new(player)), rx)
+}
+
+/// Creates a player like [`connect`], and adds it to an app.
+pub(crate) async fn add_player(app: &mut App) -> (Arc<RwLock<Player>>, UnboundedReceiver<Vec<u8>>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let player = app
+        .add_player(Player::new(tx, None, 1, Capabilities::ALL))
+        .await
+        .unwrap();
+
+    (player, rx)
+}
+
+pub(crate) async fn session_id(player: &Arc<RwLock<Player>>) -> Uuid {
+    *player.read().await.get_session_id()
+}
@@ -1,46 +1,271 @@
-use std::array::TryFromSliceError;
-use std::fmt::{Display, Formatter};
+use std::fmt::{Debug, Display, Formatter};
+use std::str::FromStr;
 
-pub const CODE_SIZE: usize = 4;
+use rand::Rng;
+use thiserror::Error;
 
+/// Maximum length of a code, check character included.
+pub const MAX_CODE_LEN: usize = 16;
+
+/// Digits and uppercase letters, without I, L, O and U.
+pub const CROCKFORD_BASE32: &str = "0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// Uppercase letters, without I and O.
+pub const UNAMBIGUOUS_LETTERS: &str = "ABCDEFGHJKLMNPQRSTUVWXYZ";
+
+/// Characters commonly mistaken for another one, replaced when they are not part of the alphabet.
+const ALIASES: [(u8, u8); 3] = [(b'O', b'0'), (b'I', b'1'), (b'L', b'1')];
+
+#[derive(Error, Debug, Eq, PartialEq)]
+pub enum CodeError {
+    #[error("invalid length {len:?}")]
+    InvalidLength { len: usize },
+    #[error("invalid character {c:?}")]
+    InvalidCharacter { c: char },
+    #[error("invalid check character")]
+    InvalidCheckCharacter,
+    #[error("invalid alphabet")]
+    InvalidAlphabet,
+}
+
+/// Room code, made of uppercase ASCII letters and digits.
 #[derive(Copy, Clone, Eq, Hash, PartialEq)]
-#[repr(transparent)]
-pub struct Code([u8; CODE_SIZE]);
+pub struct Code {
+    len: u8,
+    chars: [u8; MAX_CODE_LEN],
+}
 
 impl Code {
-    pub fn new() -> Self {
-        Code(rand::random())
+    pub fn as_str(&self) -> &str {
+        // SAFETY: a Code is only built from ASCII characters
+        unsafe { std::str::from_utf8_unchecked(self.as_slice()) }
     }
 
     pub(crate) fn as_slice(&self) -> &[u8] {
-        self.0.as_slice()
+        &self.chars[..self.len as usize]
     }
 }
 
 impl TryFrom<&[u8]> for Code {
-    type Error = TryFromSliceError;
+    type Error = CodeError;
 
+    /// Builds a code from its canonical form, without checking it against a [`CodeFormat`].
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        Ok(Code(value.try_into()?))
-    }
-}
+        if value.is_empty() || value.len() > MAX_CODE_LEN {
+            return Err(CodeError::InvalidLength { len: value.len() });
+        }
 
-impl<'a> TryFrom<&'a [u8]> for &'a Code {
-    type Error = TryFromSliceError;
+        if let Some(&c) = value
+            .iter()
+            .find(|c| !c.is_ascii_uppercase() && !c.is_ascii_digit())
+        {
+            return Err(CodeError::InvalidCharacter { c: c as char });
+        }
 
-    fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
-        let inner: &'a [u8; 4] = value.try_into()?;
+        let mut chars = [0; MAX_CODE_LEN];
+        chars[..value.len()].copy_from_slice(value);
 
-        // SAFETY: Code is #[repr(transparent)], and value.len() was checked with value.try_into()
-        let code = unsafe { &*(inner as *const [u8; 4] as *const Code) };
+        Ok(Code {
+            len: value.len() as u8,
+            chars,
+        })
+    }
+}
+
+impl FromStr for Code {
+    type Err = CodeError;
 
-        Ok(code)
+    /// Parses a code in the default [`CodeFormat`].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CodeFormat::default().parse(s)
     }
 }
 
 impl Display for Code {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "#{}", hex::encode(self.0))
+        f.write_str(self.as_str())
+    }
+}
+
+impl Debug for Code {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Code({})", self.as_str())
+    }
+}
+
+/// Alphabet and length of the room codes.
+///
+/// Codes may end with a check character, computed with the Luhn mod N algorithm, which catches
+/// every single mistyped character and most swapped pairs.
+#[derive(Clone, Debug)]
+pub struct CodeFormat {
+    alphabet: Vec<u8>,
+    length: usize,
+    check_character: bool,
+}
+
+impl Default for CodeFormat {
+    fn default() -> Self {
+        Self::new(CROCKFORD_BASE32, 5, true).unwrap()
+    }
+}
+
+impl CodeFormat {
+    /// `alphabet` must be made of at least two distinct uppercase ASCII letters and digits.
+    /// `length` is the number of random characters, the check character excluded.
+    pub fn new(alphabet: &str, length: usize, check_character: bool) -> Result<Self, CodeError> {
+        let alphabet = alphabet.as_bytes().to_vec();
+
+        let is_valid = alphabet.len() >= 2
+            && alphabet
+                .iter()
+                .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+            && alphabet
+                .iter()
+                .enumerate()
+                .all(|(i, c)| !alphabet[..i].contains(c));
+        if !is_valid {
+            return Err(CodeError::InvalidAlphabet);
+        }
+
+        let len = length + check_character as usize;
+        if length == 0 || len > MAX_CODE_LEN {
+            return Err(CodeError::InvalidLength { len });
+        }
+
+        Ok(Self {
+            alphabet,
+            length,
+            check_character,
+        })
+    }
+
+    pub fn alphabet(&self) -> &str {
+        // SAFETY: the alphabet is checked to be made of ASCII characters
+        unsafe { std::str::from_utf8_unchecked(&self.alphabet) }
+    }
+
+    /// Number of random characters, the check character excluded.
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    pub fn has_check_character(&self) -> bool {
+        self.check_character
+    }
+
+    /// Total length of the codes, check character included.
+    pub fn code_len(&self) -> usize {
+        self.length + self.check_character as usize
+    }
+
+    /// Number of distinct codes in this format.
+    pub fn code_space(&self) -> u128 {
+        (self.alphabet.len() as u128).saturating_pow(self.length as u32)
+    }
+
+    pub fn generate(&self) -> Code {
+        let mut rng = rand::rng();
+        let mut chars = [0; MAX_CODE_LEN];
+
+        for c in chars.iter_mut().take(self.length) {
+            *c = self.alphabet[rng.random_range(0..self.alphabet.len())];
+        }
+        if self.check_character {
+            chars[self.length] = self.check_char(&chars[..self.length]);
+        }
+
+        Code {
+            len: self.code_len() as u8,
+            chars,
+        }
+    }
+
+    /// Parses a code typed by a player.
+    ///
+    /// Case, separators (`-` and spaces) and a leading `#` are ignored, and characters easily
+    /// mistaken for a digit (`O`, `I` and `L`) are accepted in its place.
+    pub fn parse(&self, s: &str) -> Result<Code, CodeError> {
+        self.parse_chars(s, false)
+    }
+
+    /// Parses a code chosen ahead of time, such as a vanity code, like [`CodeFormat::parse`].
+    ///
+    /// The check character may be omitted: it is then computed and appended, and players have to
+    /// type the complete code.
+    pub fn complete(&self, s: &str) -> Result<Code, CodeError> {
+        self.parse_chars(s, true)
+    }
+
+    fn parse_chars(&self, s: &str, complete: bool) -> Result<Code, CodeError> {
+        let s = s.strip_prefix('#').unwrap_or(s);
+
+        let mut chars = [0; MAX_CODE_LEN];
+        let mut len = 0;
+
+        for c in s.chars().filter(|&c| c != '-' && !c.is_whitespace()) {
+            if !c.is_ascii() {
+                return Err(CodeError::InvalidCharacter { c });
+            }
+
+            let c = self
+                .normalize(c.to_ascii_uppercase() as u8)
+                .ok_or(CodeError::InvalidCharacter { c })?;
+
+            if len == self.code_len() {
+                return Err(CodeError::InvalidLength {
+                    len: s.chars().count(),
+                });
+            }
+            chars[len] = c;
+            len += 1;
+        }
+
+        if complete && self.check_character && len == self.length {
+            chars[len] = self.check_char(&chars[..len]);
+            len += 1;
+        }
+
+        if len != self.code_len() {
+            return Err(CodeError::InvalidLength { len });
+        }
+
+        if self.check_character && self.check_char(&chars[..self.length]) != chars[self.length] {
+            return Err(CodeError::InvalidCheckCharacter);
+        }
+
+        Ok(Code {
+            len: len as u8,
+            chars,
+        })
+    }
+
+    /// Returns the character of the alphabet matching an uppercase character.
+    fn normalize(&self, c: u8) -> Option<u8> {
+        if self.alphabet.contains(&c) {
+            return Some(c);
+        }
+
+        ALIASES
+            .iter()
+            .find(|(alias, target)| *alias == c && self.alphabet.contains(target))
+            .map(|(_, target)| *target)
+    }
+
+    /// Luhn mod N check character of a code.
+    fn check_char(&self, chars: &[u8]) -> u8 {
+        let n = self.alphabet.len();
+        let mut factor = 2;
+        let mut sum = 0;
+
+        for c in chars.iter().rev() {
+            let index = self.alphabet.iter().position(|a| a == c).unwrap();
+            let addend = factor * index;
+            sum += addend / n + addend % n;
+            factor = if factor == 2 { 1 } else { 2 };
+        }
+
+        self.alphabet[(n - sum % n) % n]
     }
 }
 
@@ -49,11 +274,44 @@ mod tests {
     use super::*;
 
     #[test]
-    fn from_ref() {
-        let buf: &[u8; 4] = &[1, 2, 3, 4];
+    fn parse_generated() {
+        let format = CodeFormat::default();
+        let code = format.generate();
+
+        assert_eq!(6, code.as_str().len());
+        assert_eq!(code, format.parse(code.as_str()).unwrap());
+        assert_eq!(code, format.parse(&code.as_str().to_lowercase()).unwrap());
+    }
+
+    #[test]
+    fn parse_aliases_and_separators() {
+        let format = CodeFormat::new(CROCKFORD_BASE32, 4, false).unwrap();
+
+        assert_eq!("1001", format.parse("#i-O 0l").unwrap().as_str());
+    }
+
+    #[test]
+    fn parse_rejects_typos() {
+        let format = CodeFormat::new(UNAMBIGUOUS_LETTERS, 5, true).unwrap();
+        let code = format.generate();
 
-        let code: &Code = buf[0..4].try_into().unwrap();
+        let mut typo = code.as_slice().to_vec();
+        typo[0] = if typo[0] == b'A' { b'B' } else { b'A' };
+
+        assert_eq!(
+            Err(CodeError::InvalidCheckCharacter),
+            format.parse(std::str::from_utf8(&typo).unwrap())
+        );
+    }
+
+    #[test]
+    fn complete_appends_the_check_character() {
+        let format = CodeFormat::default();
+        let code = format.complete("final").unwrap();
 
-        assert_eq!([1, 2, 3, 4], code.0);
+        assert_eq!("F1NA1", &code.as_str()[..5]);
+        assert_eq!(code, format.parse(code.as_str()).unwrap());
+        assert_eq!(code, format.complete(code.as_str()).unwrap());
+        assert!(format.parse("final").is_err());
     }
 }
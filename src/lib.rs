@@ -1,4 +1,4 @@
 pub mod app;
-pub(crate) mod code;
+pub mod code;
 pub mod proto;
 pub mod server;
@@ -1,25 +1,83 @@
 use std::env;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
-use ws_relay::app::App;
+use ws_relay::app::{App, Config};
+use ws_relay::code::{self, CodeFormat};
 use ws_relay::server;
 
 const DEFAULT_PORT: u16 = 8080;
 const PORT_ENV: &str = "PORT";
+const RESUME_GRACE_PERIOD_ENV: &str = "RESUME_GRACE_PERIOD";
+/// `crockford`, `letters`, or the characters to use.
+const ROOM_CODE_ALPHABET_ENV: &str = "ROOM_CODE_ALPHABET";
+const ROOM_CODE_LENGTH_ENV: &str = "ROOM_CODE_LENGTH";
+/// `true` or `false`.
+const ROOM_CODE_CHECK_ENV: &str = "ROOM_CODE_CHECK";
+const MAX_ROOMS_ENV: &str = "MAX_ROOMS";
 
 #[tokio::main]
 async fn main() {
     let port = match env::var(PORT_ENV) {
         Ok(p) => p
             .parse::<u16>()
-            .expect(format!("invalid PORT env \"{}\"", p).as_str()),
+            .unwrap_or_else(|_| panic!("invalid PORT env \"{}\"", p)),
         Err(_) => DEFAULT_PORT,
     };
 
-    let app = Arc::new(RwLock::new(App::new()));
+    let mut config = Config::default();
+
+    if let Ok(s) = env::var(RESUME_GRACE_PERIOD_ENV) {
+        config.resume_grace_period = Duration::from_secs(
+            s.parse::<u64>()
+                .unwrap_or_else(|_| panic!("invalid {} env \"{}\"", RESUME_GRACE_PERIOD_ENV, s)),
+        );
+    }
+
+    config.code_format = room_code_format();
+
+    if let Ok(s) = env::var(MAX_ROOMS_ENV) {
+        config.max_rooms = Some(
+            s.parse::<usize>()
+                .unwrap_or_else(|_| panic!("invalid {} env \"{}\"", MAX_ROOMS_ENV, s)),
+        );
+    }
+
+    let app = Arc::new(RwLock::new(App::with_config(config)));
 
     let addr = SocketAddr::from(([0, 0, 0, 0], port));
 
     server::run(&addr, app).await;
 }
+
+/// Room code format from the environment, the unset parts taken from the default format.
+fn room_code_format() -> CodeFormat {
+    let default = CodeFormat::default();
+
+    let alphabet = match env::var(ROOM_CODE_ALPHABET_ENV) {
+        Ok(s) => match s.as_str() {
+            "crockford" => code::CROCKFORD_BASE32.to_string(),
+            "letters" => code::UNAMBIGUOUS_LETTERS.to_string(),
+            _ => s,
+        },
+        Err(_) => default.alphabet().to_string(),
+    };
+
+    let length = match env::var(ROOM_CODE_LENGTH_ENV) {
+        Ok(s) => s
+            .parse::<usize>()
+            .unwrap_or_else(|_| panic!("invalid {} env \"{}\"", ROOM_CODE_LENGTH_ENV, s)),
+        Err(_) => default.length(),
+    };
+
+    let check_character = match env::var(ROOM_CODE_CHECK_ENV) {
+        Ok(s) => s
+            .parse::<bool>()
+            .unwrap_or_else(|_| panic!("invalid {} env \"{}\"", ROOM_CODE_CHECK_ENV, s)),
+        Err(_) => default.has_check_character(),
+    };
+
+    CodeFormat::new(&alphabet, length, check_character)
+        .unwrap_or_else(|e| panic!("invalid room code format: {}", e))
+}
@@ -1,13 +1,306 @@
 use bytes::{Buf, BufMut};
+use uuid::Uuid;
 
-use crate::code::{self, Code};
+use crate::proto::codec::{
+    decode_header, decode_uuid, encode_header, encode_uuid, get_bytes_u16, get_bytes_u8,
+    get_str_u8, get_u16, get_u32, put_bytes_u16, put_bytes_u8, UUID_LEN,
+};
 use crate::proto::error::{DecodeError, EncodeError};
-use crate::proto::ForwardMessage;
+use crate::proto::{Capabilities, ForwardMessage, RequestId, ResumeToken, RESUME_TOKEN_LEN};
 
 pub enum Message<'a> {
     SendToPlayer(ForwardMessage<'a>),
-    CreateRoom,
-    JoinRoom { code: &'a Code },
+    CreateRoom(RoomOptions<'a>),
+    JoinRoom {
+        /// Room code as typed by the player, parsed with the server's
+        /// [`CodeFormat`](crate::code::CodeFormat).
+        code: &'a str,
+        /// Required to join rooms created with a password.
+        password: Option<&'a [u8]>,
+        /// Whether the player joins as a spectator.
+        spectator: bool,
+    },
+    LeaveRoom,
+    TransferHost {
+        session_id: &'a Uuid,
+    },
+    /// First message of a connection, announcing what the client supports.
+    ///
+    /// A client resuming a previous session sends the resume token it was given.
+    Hello {
+        protocol_version: u16,
+        capabilities: Capabilities,
+        resume_token: Option<&'a ResumeToken>,
+    },
+    /// Asks for the public rooms matching a filter, replied to with a `RoomList`.
+    ListRooms {
+        filter: RoomFilter<'a>,
+    },
+    /// Removes a player from the room. Only the host may send it.
+    KickPlayer {
+        session_id: &'a Uuid,
+        /// Opaque reason forwarded to the kicked player.
+        reason: &'a [u8],
+    },
+    /// Prevents a player from joining the room, kicking it if it is a member. Only the host may
+    /// send it.
+    BanPlayer {
+        session_id: &'a Uuid,
+        /// Whether the player's IP address is banned too.
+        by_ip: bool,
+    },
+    /// Lifts a ban. Only the host may send it.
+    UnbanPlayer {
+        session_id: &'a Uuid,
+    },
+    /// Forwards a payload to every other member of the room.
+    BroadcastToRoom {
+        raw: &'a [u8],
+    },
+    /// Forwards a payload to several members of the room.
+    Multicast {
+        targets: Vec<&'a Uuid>,
+        raw: &'a [u8],
+    },
+    /// Sets a value of the room, shared with every member.
+    SetRoomValue {
+        key: &'a [u8],
+        value: &'a [u8],
+    },
+    DeleteRoomValue {
+        key: &'a [u8],
+    },
+    /// Sets an entry of the sender's data, shared with the members of its room. An empty value
+    /// deletes the entry.
+    SetPlayerData {
+        key: &'a [u8],
+        value: &'a [u8],
+    },
+    /// Tells the room whether the sender is ready for the game to start.
+    SetReady {
+        ready: bool,
+    },
+    /// Starts the countdown to the game, once every member is ready. Only the host may send it.
+    StartGame,
+    /// Ends the game in progress. Only the host may send it.
+    EndGame,
+    /// Waits for other players to form a room with. The players entering the same queue with the
+    /// same party size and attributes are matched together.
+    EnterQueue {
+        queue_name: &'a str,
+        /// Number of players in the room formed.
+        party_size: u8,
+        attributes: &'a [u8],
+    },
+    LeaveQueue,
+    /// Joins the fullest open public room matching a filter, or creates one if there is none.
+    ///
+    /// Replied to with `RoomJoined`, or `RoomCreated` if a room was created.
+    QuickJoin {
+        filter: RoomFilter<'a>,
+        /// Capacity of the room created if there is none, `0` for no limit.
+        max_players: u8,
+    },
+    /// Forwards a payload to every spectator of the room. Only the host may send it.
+    BroadcastToSpectators {
+        raw: &'a [u8],
+    },
+    /// Starts receiving the payloads published to a topic of the room. Topics are at most 255
+    /// bytes long.
+    Subscribe {
+        topic: &'a [u8],
+    },
+    Unsubscribe {
+        topic: &'a [u8],
+    },
+    /// Forwards a payload to every other member of the room subscribed to a topic.
+    PublishToTopic {
+        topic: &'a [u8],
+        raw: &'a [u8],
+    },
+}
+
+/// Options sent along with `CreateRoom`.
+///
+/// Every field is optional on the wire: a client may omit any trailing fields, which then take
+/// their default value.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RoomOptions<'a> {
+    pub host_migration: HostMigration,
+    /// Maximum number of players in the room, or `0` for no limit.
+    pub max_players: u8,
+    /// Password the players must send to join the room. An empty password is the same as none.
+    pub password: Option<&'a [u8]>,
+    /// Whether the room is listed by `ListRooms`.
+    pub public: bool,
+    /// Opaque data describing the room to other players, such as its name or game mode.
+    pub metadata: &'a [u8],
+    /// Code requested for the room, instead of a random one. An empty code is the same as none.
+    ///
+    /// Its check character may be omitted, the complete code being sent back in `RoomCreated`.
+    pub code: Option<&'a str>,
+    /// Whether only the host may set the values of the room.
+    pub host_only_values: bool,
+    /// Number of `BroadcastToRoom` payloads kept by the room and replayed to the players joining
+    /// it, or `0` to keep none. Capped by the server.
+    pub history_len: u16,
+    /// Maximum total size of the payloads kept, in bytes, or `0` for the server's limit. Capped by
+    /// the server.
+    pub history_max_bytes: u32,
+    /// Maximum age of the payloads kept, in seconds, or `0` for no limit.
+    pub history_max_age: u16,
+}
+
+/// Criteria the rooms listed by `ListRooms` must match.
+///
+/// Like [`RoomOptions`], trailing fields may be omitted on the wire.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RoomFilter<'a> {
+    /// Whether full rooms are listed too.
+    pub include_full: bool,
+    /// Only the rooms whose metadata starts with these bytes are listed.
+    pub metadata_prefix: &'a [u8],
+}
+
+/// What happens to a room when its host leaves.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[repr(u8)]
+pub enum HostMigration {
+    /// The room is closed.
+    #[default]
+    Disabled = 0,
+    /// The member connected for the longest time becomes the host.
+    LongestConnected = 1,
+}
+
+impl TryFrom<u8> for HostMigration {
+    type Error = DecodeError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(HostMigration::Disabled),
+            1 => Ok(HostMigration::LongestConnected),
+            v => Err(DecodeError::BadValue {
+                field: "host_migration",
+                value: v,
+            }),
+        }
+    }
+}
+
+impl<'a> RoomOptions<'a> {
+    fn encode<B>(&self, buf: &mut B) -> Result<(), EncodeError>
+    where
+        B: BufMut,
+    {
+        buf.put_u8(self.host_migration as u8);
+        buf.put_u8(self.max_players);
+        put_bytes_u8(buf, self.password.unwrap_or_default())?;
+        buf.put_u8(self.public as u8);
+        put_bytes_u16(buf, self.metadata)?;
+        put_bytes_u8(buf, self.code.unwrap_or_default().as_bytes())?;
+        buf.put_u8(self.host_only_values as u8);
+        buf.put_u16(self.history_len);
+        buf.put_u32(self.history_max_bytes);
+        buf.put_u16(self.history_max_age);
+        Ok(())
+    }
+
+    fn decode(mut buf: &'a [u8]) -> Result<Self, DecodeError> {
+        let mut options = RoomOptions::default();
+
+        if buf.has_remaining() {
+            options.host_migration = buf.get_u8().try_into()?;
+        }
+        if buf.has_remaining() {
+            options.max_players = buf.get_u8();
+        }
+        if buf.has_remaining() {
+            options.password = Some(get_bytes_u8(&mut buf)?).filter(|p| !p.is_empty());
+        }
+        if buf.has_remaining() {
+            options.public = buf.get_u8() != 0;
+        }
+        if buf.has_remaining() {
+            options.metadata = get_bytes_u16(&mut buf)?;
+        }
+        if buf.has_remaining() {
+            options.code = Some(get_str_u8(&mut buf, "code")?).filter(|c| !c.is_empty());
+        }
+        if buf.has_remaining() {
+            options.host_only_values = buf.get_u8() != 0;
+        }
+        if buf.has_remaining() {
+            options.history_len = get_u16(&mut buf)?;
+        }
+        if buf.has_remaining() {
+            options.history_max_bytes = get_u32(&mut buf)?;
+        }
+        if buf.has_remaining() {
+            options.history_max_age = get_u16(&mut buf)?;
+        }
+
+        Ok(options)
+    }
+}
+
+impl<'a> RoomFilter<'a> {
+    fn encode<B>(&self, buf: &mut B) -> Result<(), EncodeError>
+    where
+        B: BufMut,
+    {
+        buf.put_u8(self.include_full as u8);
+        put_bytes_u16(buf, self.metadata_prefix)
+    }
+
+    fn decode(buf: &mut &'a [u8]) -> Result<Self, DecodeError> {
+        let mut filter = RoomFilter::default();
+
+        if buf.has_remaining() {
+            filter.include_full = buf.get_u8() != 0;
+        }
+        if buf.has_remaining() {
+            filter.metadata_prefix = get_bytes_u16(buf)?;
+        }
+
+        Ok(filter)
+    }
+}
+
+/// A c2s message, optionally tagged by the client with a request id.
+///
+/// The request id is echoed back in the reply to the message: `RoomCreated`, `RoomJoined`, `Error`,
+/// or `Ack` for the messages having no other reply.
+pub struct Request<'a> {
+    pub request_id: Option<RequestId>,
+    pub message: Message<'a>,
+}
+
+impl<'a> Request<'a> {
+    pub fn encode<B>(&self, buf: &mut B) -> Result<(), EncodeError>
+    where
+        B: BufMut,
+    {
+        encode_header(buf, self.message.type_code(), self.request_id);
+        self.message.encode_body(buf)
+    }
+
+    pub fn decode(buf: &'a [u8]) -> Result<Self, DecodeError> {
+        let (type_code, request_id, body) = decode_header(buf)?;
+
+        Ok(Request {
+            request_id,
+            message: Message::decode_body(type_code, body)?,
+        })
+    }
+
+    /// Reads what it can of the type code and request id of a message, even an invalid one.
+    pub fn peek_header(buf: &[u8]) -> (u8, Option<RequestId>) {
+        match decode_header(buf) {
+            Ok((type_code, request_id, _)) => (type_code, request_id),
+            Err(_) => (0, None),
+        }
+    }
 }
 
 impl<'a> Message<'a> {
@@ -15,34 +308,323 @@ impl<'a> Message<'a> {
     where
         B: BufMut,
     {
-        buf.put_u8(self.type_code());
+        encode_header(buf, self.type_code(), None);
+        self.encode_body(buf)
+    }
 
+    fn encode_body<B>(&self, buf: &mut B) -> Result<(), EncodeError>
+    where
+        B: BufMut,
+    {
         match self {
             Message::SendToPlayer(fwd) => fwd.encode(buf),
-            Message::CreateRoom => Ok(()),
-            Message::JoinRoom { code } => Ok(buf.put_slice(code.as_slice())),
+            Message::CreateRoom(options) => options.encode(buf),
+            Message::JoinRoom {
+                code,
+                password,
+                spectator,
+            } => {
+                put_bytes_u8(buf, code.as_bytes())?;
+                if password.is_some() || *spectator {
+                    put_bytes_u8(buf, password.unwrap_or_default())?;
+                }
+                if *spectator {
+                    buf.put_u8(1);
+                }
+                Ok(())
+            }
+            Message::LeaveRoom => Ok(()),
+            Message::TransferHost { session_id } => encode_uuid(buf, session_id),
+            Message::Hello {
+                protocol_version,
+                capabilities,
+                resume_token,
+            } => {
+                buf.put_u16(*protocol_version);
+                buf.put_u32(capabilities.bits());
+                if let Some(resume_token) = resume_token {
+                    buf.put_slice(*resume_token);
+                }
+                Ok(())
+            }
+            Message::ListRooms { filter } => filter.encode(buf),
+            Message::KickPlayer { session_id, reason } => {
+                encode_uuid(buf, session_id)?;
+                buf.put_slice(reason);
+                Ok(())
+            }
+            Message::BanPlayer { session_id, by_ip } => {
+                encode_uuid(buf, session_id)?;
+                buf.put_u8(*by_ip as u8);
+                Ok(())
+            }
+            Message::UnbanPlayer { session_id } => encode_uuid(buf, session_id),
+            Message::BroadcastToRoom { raw } => {
+                buf.put_slice(raw);
+                Ok(())
+            }
+            Message::Multicast { targets, raw } => {
+                let len = targets.len();
+                if len > u8::MAX as usize {
+                    return Err(EncodeError::TooLong {
+                        max: u8::MAX as usize,
+                        len,
+                    });
+                }
+
+                buf.put_u8(len as u8);
+                for target in targets {
+                    encode_uuid(buf, target)?;
+                }
+                buf.put_slice(raw);
+                Ok(())
+            }
+            Message::SetRoomValue { key, value } => {
+                put_bytes_u8(buf, key)?;
+                buf.put_slice(value);
+                Ok(())
+            }
+            Message::DeleteRoomValue { key } => {
+                buf.put_slice(key);
+                Ok(())
+            }
+            Message::SetPlayerData { key, value } => {
+                put_bytes_u8(buf, key)?;
+                buf.put_slice(value);
+                Ok(())
+            }
+            Message::SetReady { ready } => {
+                buf.put_u8(*ready as u8);
+                Ok(())
+            }
+            Message::StartGame => Ok(()),
+            Message::EndGame => Ok(()),
+            Message::EnterQueue {
+                queue_name,
+                party_size,
+                attributes,
+            } => {
+                put_bytes_u8(buf, queue_name.as_bytes())?;
+                buf.put_u8(*party_size);
+                buf.put_slice(attributes);
+                Ok(())
+            }
+            Message::LeaveQueue => Ok(()),
+            Message::QuickJoin {
+                filter,
+                max_players,
+            } => {
+                filter.encode(buf)?;
+                if *max_players != 0 {
+                    buf.put_u8(*max_players);
+                }
+                Ok(())
+            }
+            Message::BroadcastToSpectators { raw } => {
+                buf.put_slice(raw);
+                Ok(())
+            }
+            Message::Subscribe { topic } | Message::Unsubscribe { topic } => {
+                put_bytes_u8(buf, topic)
+            }
+            Message::PublishToTopic { topic, raw } => {
+                put_bytes_u8(buf, topic)?;
+                buf.put_slice(raw);
+                Ok(())
+            }
         }
     }
 
     pub fn decode(buf: &'a [u8]) -> Result<Self, DecodeError> {
-        let remaining = buf.remaining();
-
-        if remaining < 1 {
-            return Err(DecodeError::BufferTooSmall { min: 1, remaining });
-        }
+        Request::decode(buf).map(|request| request.message)
+    }
 
-        match buf[0] {
-            1 => Ok(Message::SendToPlayer(ForwardMessage::decode(&buf[1..])?)),
-            2 => Ok(Message::CreateRoom),
+    fn decode_body(type_code: u8, body: &'a [u8]) -> Result<Self, DecodeError> {
+        match type_code {
+            1 => Ok(Message::SendToPlayer(ForwardMessage::decode(body)?)),
+            2 => Ok(Message::CreateRoom(RoomOptions::decode(body)?)),
             3 => {
-                let min = code::CODE_SIZE + 1;
+                let mut rest = body;
+                let code = get_str_u8(&mut rest, "code")?;
+                let password = if rest.is_empty() {
+                    None
+                } else {
+                    Some(get_bytes_u8(&mut rest)?).filter(|p| !p.is_empty())
+                };
+                let spectator = rest.has_remaining() && rest.get_u8() != 0;
+
+                Ok(Message::JoinRoom {
+                    code,
+                    password,
+                    spectator,
+                })
+            }
+            4 => Ok(Message::LeaveRoom),
+            5 => Ok(Message::TransferHost {
+                session_id: decode_uuid(body)?,
+            }),
+            6 => {
+                let remaining = body.len();
+                if remaining < 6 {
+                    return Err(DecodeError::BufferTooSmall { min: 6, remaining });
+                }
+
+                let resume_token = match &body[6..] {
+                    [] => None,
+                    token => Some(token.try_into().map_err(|_| DecodeError::BufferTooSmall {
+                        min: 6 + RESUME_TOKEN_LEN,
+                        remaining,
+                    })?),
+                };
+
+                Ok(Message::Hello {
+                    protocol_version: u16::from_be_bytes(body[..2].try_into().unwrap()),
+                    capabilities: Capabilities::from_bits(u32::from_be_bytes(
+                        body[2..6].try_into().unwrap(),
+                    )),
+                    resume_token,
+                })
+            }
+            7 => {
+                let mut rest = body;
+
+                Ok(Message::ListRooms {
+                    filter: RoomFilter::decode(&mut rest)?,
+                })
+            }
+            8 => {
+                let remaining = body.len();
+                if remaining < UUID_LEN {
+                    return Err(DecodeError::BufferTooSmall {
+                        min: UUID_LEN,
+                        remaining,
+                    });
+                }
+
+                Ok(Message::KickPlayer {
+                    session_id: decode_uuid(&body[..UUID_LEN])?,
+                    reason: &body[UUID_LEN..],
+                })
+            }
+            9 => {
+                const MIN_LEN: usize = UUID_LEN + 1;
+                let remaining = body.len();
+                if remaining < MIN_LEN {
+                    return Err(DecodeError::BufferTooSmall {
+                        min: MIN_LEN,
+                        remaining,
+                    });
+                }
+
+                Ok(Message::BanPlayer {
+                    session_id: decode_uuid(&body[..UUID_LEN])?,
+                    by_ip: body[UUID_LEN] != 0,
+                })
+            }
+            10 => Ok(Message::UnbanPlayer {
+                session_id: decode_uuid(body)?,
+            }),
+            11 => Ok(Message::BroadcastToRoom {
+                raw: get_payload(body)?,
+            }),
+            12 => {
+                let remaining = body.len();
+                if remaining < 1 {
+                    return Err(DecodeError::BufferTooSmall { min: 1, remaining });
+                }
 
+                let min = body[0] as usize * UUID_LEN + 1;
                 if remaining < min {
                     return Err(DecodeError::BufferTooSmall { min, remaining });
                 }
 
-                Ok(Message::JoinRoom {
-                    code: buf[1..code::CODE_SIZE + 1].try_into().unwrap(),
+                Ok(Message::Multicast {
+                    targets: body[1..min]
+                        .chunks_exact(UUID_LEN)
+                        .map(decode_uuid)
+                        .collect::<Result<_, _>>()?,
+                    raw: get_payload(&body[min..])?,
+                })
+            }
+            13 => {
+                let mut value = body;
+                let key = get_bytes_u8(&mut value)?;
+
+                Ok(Message::SetRoomValue { key, value })
+            }
+            14 => Ok(Message::DeleteRoomValue { key: body }),
+            15 => {
+                let mut value = body;
+                let key = get_bytes_u8(&mut value)?;
+
+                Ok(Message::SetPlayerData { key, value })
+            }
+            16 => {
+                let remaining = body.len();
+                if remaining < 1 {
+                    return Err(DecodeError::BufferTooSmall { min: 1, remaining });
+                }
+
+                Ok(Message::SetReady {
+                    ready: body[0] != 0,
+                })
+            }
+            17 => Ok(Message::StartGame),
+            18 => Ok(Message::EndGame),
+            19 => {
+                let mut rest = body;
+                let queue_name = get_str_u8(&mut rest, "queue_name")?;
+
+                let remaining = rest.len();
+                if remaining < 1 {
+                    return Err(DecodeError::BufferTooSmall { min: 1, remaining });
+                }
+
+                Ok(Message::EnterQueue {
+                    queue_name,
+                    party_size: rest[0],
+                    attributes: &rest[1..],
+                })
+            }
+            20 => Ok(Message::LeaveQueue),
+            21 => {
+                let mut rest = body;
+                let filter = RoomFilter::decode(&mut rest)?;
+                let max_players = if rest.has_remaining() {
+                    rest.get_u8()
+                } else {
+                    0
+                };
+
+                Ok(Message::QuickJoin {
+                    filter,
+                    max_players,
+                })
+            }
+            22 => Ok(Message::BroadcastToSpectators {
+                raw: get_payload(body)?,
+            }),
+            23 => {
+                let mut rest = body;
+
+                Ok(Message::Subscribe {
+                    topic: get_bytes_u8(&mut rest)?,
+                })
+            }
+            24 => {
+                let mut rest = body;
+
+                Ok(Message::Unsubscribe {
+                    topic: get_bytes_u8(&mut rest)?,
+                })
+            }
+            25 => {
+                let mut raw = body;
+                let topic = get_bytes_u8(&mut raw)?;
+
+                Ok(Message::PublishToTopic {
+                    topic,
+                    raw: get_payload(raw)?,
                 })
             }
             c => Err(DecodeError::BadMessageCode { code: c }),
@@ -52,8 +634,159 @@ impl<'a> Message<'a> {
     const fn type_code(&self) -> u8 {
         match self {
             Message::SendToPlayer { .. } => 1,
-            Message::CreateRoom => 2,
+            Message::CreateRoom(_) => 2,
             Message::JoinRoom { .. } => 3,
+            Message::LeaveRoom => 4,
+            Message::TransferHost { .. } => 5,
+            Message::Hello { .. } => 6,
+            Message::ListRooms { .. } => 7,
+            Message::KickPlayer { .. } => 8,
+            Message::BanPlayer { .. } => 9,
+            Message::UnbanPlayer { .. } => 10,
+            Message::BroadcastToRoom { .. } => 11,
+            Message::Multicast { .. } => 12,
+            Message::SetRoomValue { .. } => 13,
+            Message::DeleteRoomValue { .. } => 14,
+            Message::SetPlayerData { .. } => 15,
+            Message::SetReady { .. } => 16,
+            Message::StartGame => 17,
+            Message::EndGame => 18,
+            Message::EnterQueue { .. } => 19,
+            Message::LeaveQueue => 20,
+            Message::QuickJoin { .. } => 21,
+            Message::BroadcastToSpectators { .. } => 22,
+            Message::Subscribe { .. } => 23,
+            Message::Unsubscribe { .. } => 24,
+            Message::PublishToTopic { .. } => 25,
+        }
+    }
+}
+
+/// Checks that a payload to relay is not empty, as `ReceiveFromPlayer` can't carry an empty one.
+fn get_payload(raw: &[u8]) -> Result<&[u8], DecodeError> {
+    if raw.is_empty() {
+        return Err(DecodeError::BufferTooSmall {
+            min: 1,
+            remaining: 0,
+        });
+    }
+
+    Ok(raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multicast_round_trip() {
+        let targets = [Uuid::new_v4(), Uuid::new_v4()];
+
+        let mut buf = vec![];
+        Message::Multicast {
+            targets: targets.iter().collect(),
+            raw: &[1, 2, 3],
+        }
+        .encode(&mut buf)
+        .unwrap();
+
+        match Message::decode(&buf).unwrap() {
+            Message::Multicast {
+                targets: decoded,
+                raw,
+            } => {
+                assert_eq!(targets.iter().collect::<Vec<_>>(), decoded);
+                assert_eq!(&[1, 2, 3], raw);
+            }
+            _ => panic!("decoded the wrong message"),
+        }
+    }
+
+    #[test]
+    fn quick_join_capacity_is_optional() {
+        let filter = RoomFilter {
+            include_full: false,
+            metadata_prefix: b"party",
+        };
+
+        for max_players in [0, 4] {
+            let mut buf = vec![];
+            Message::QuickJoin {
+                filter,
+                max_players,
+            }
+            .encode(&mut buf)
+            .unwrap();
+
+            match Message::decode(&buf).unwrap() {
+                Message::QuickJoin {
+                    filter: decoded,
+                    max_players: decoded_max_players,
+                } => {
+                    assert_eq!(b"party", decoded.metadata_prefix);
+                    assert_eq!(max_players, decoded_max_players);
+                }
+                _ => panic!("decoded the wrong message"),
+            }
+        }
+    }
+
+    #[test]
+    fn topics_are_length_prefixed() {
+        let mut buf = vec![];
+        Message::Subscribe { topic: b"chat" }
+            .encode(&mut buf)
+            .unwrap();
+        assert!(matches!(
+            Message::decode(&buf),
+            Ok(Message::Subscribe { topic: b"chat" })
+        ));
+
+        let topic = [b'a'; 256];
+        for msg in [
+            Message::Subscribe { topic: &topic },
+            Message::Unsubscribe { topic: &topic },
+            Message::PublishToTopic {
+                topic: &topic,
+                raw: b"hi",
+            },
+        ] {
+            assert!(matches!(
+                msg.encode(&mut vec![]),
+                Err(EncodeError::TooLong { max: 255, len: 256 })
+            ));
+        }
+
+        // A topic longer than the frame is rejected
+        assert!(matches!(
+            Message::decode(&[23, 5, b'c', b'h']),
+            Err(DecodeError::BufferTooSmall { min: 6, .. })
+        ));
+    }
+
+    #[test]
+    fn empty_payloads_are_rejected() {
+        let target = Uuid::new_v4();
+
+        for msg in [
+            Message::BroadcastToRoom { raw: &[] },
+            Message::BroadcastToSpectators { raw: &[] },
+            Message::PublishToTopic {
+                topic: b"chat",
+                raw: &[],
+            },
+            Message::Multicast {
+                targets: vec![&target],
+                raw: &[],
+            },
+        ] {
+            let mut buf = vec![];
+            msg.encode(&mut buf).unwrap();
+
+            assert!(matches!(
+                Message::decode(&buf),
+                Err(DecodeError::BufferTooSmall { min: 1, .. })
+            ));
         }
     }
 }
@@ -0,0 +1,237 @@
+use bytes::BufMut;
+use uuid::Uuid;
+
+use crate::code::Code;
+use crate::proto::error::{DecodeError, EncodeError};
+use crate::proto::RequestId;
+
+/// Set on the type code of a message carrying a request id.
+const REQUEST_ID_FLAG: u8 = 0x80;
+
+const REQUEST_ID_LEN: usize = 4;
+
+pub(crate) const UUID_LEN: usize = 16;
+
+pub(crate) fn encode_uuid<B>(buf: &mut B, uuid: &Uuid) -> Result<(), EncodeError>
+where
+    B: BufMut,
+{
+    let remaining = buf.remaining_mut();
+    if remaining < 16 {
+        return Err(EncodeError::InsufficientCapacity {
+            required: 16,
+            remaining,
+        });
+    }
+
+    buf.put_slice(uuid.as_bytes());
+    Ok(())
+}
+
+pub(crate) fn decode_uuid(buf: &[u8]) -> Result<&Uuid, DecodeError> {
+    let len = buf.len();
+    if len != 16 {
+        return Err(DecodeError::BufferTooSmall {
+            remaining: len,
+            min: 16,
+        });
+    }
+
+    let b: &[u8; 16] = buf[0..16].try_into().unwrap();
+    Ok(Uuid::from_bytes_ref(b))
+}
+
+/// Writes the type code of a message, followed by its request id if any.
+pub(crate) fn encode_header<B>(buf: &mut B, type_code: u8, request_id: Option<RequestId>)
+where
+    B: BufMut,
+{
+    match request_id {
+        Some(request_id) => {
+            buf.put_u8(type_code | REQUEST_ID_FLAG);
+            buf.put_u32(request_id);
+        }
+        None => buf.put_u8(type_code),
+    }
+}
+
+/// Reads the type code and the request id of a message, returning them along with its body.
+pub(crate) fn decode_header(buf: &[u8]) -> Result<(u8, Option<RequestId>, &[u8]), DecodeError> {
+    let remaining = buf.len();
+    if remaining < 1 {
+        return Err(DecodeError::BufferTooSmall { min: 1, remaining });
+    }
+
+    let type_code = buf[0];
+    if type_code & REQUEST_ID_FLAG == 0 {
+        return Ok((type_code, None, &buf[1..]));
+    }
+
+    const MIN_LEN: usize = REQUEST_ID_LEN + 1;
+    if remaining < MIN_LEN {
+        return Err(DecodeError::BufferTooSmall {
+            min: MIN_LEN,
+            remaining,
+        });
+    }
+
+    let request_id = RequestId::from_be_bytes(buf[1..MIN_LEN].try_into().unwrap());
+
+    Ok((
+        type_code & !REQUEST_ID_FLAG,
+        Some(request_id),
+        &buf[MIN_LEN..],
+    ))
+}
+
+/// Writes bytes prefixed by their length, on one byte.
+pub(crate) fn put_bytes_u8<B>(buf: &mut B, bytes: &[u8]) -> Result<(), EncodeError>
+where
+    B: BufMut,
+{
+    let len = bytes.len();
+    if len > u8::MAX as usize {
+        return Err(EncodeError::TooLong {
+            max: u8::MAX as usize,
+            len,
+        });
+    }
+
+    buf.put_u8(len as u8);
+    buf.put_slice(bytes);
+    Ok(())
+}
+
+/// Reads bytes prefixed by their length, on one byte, and advances `buf` past them.
+pub(crate) fn get_bytes_u8<'a>(buf: &mut &'a [u8]) -> Result<&'a [u8], DecodeError> {
+    let remaining = buf.len();
+    if remaining < 1 {
+        return Err(DecodeError::BufferTooSmall { min: 1, remaining });
+    }
+
+    let min = buf[0] as usize + 1;
+    if remaining < min {
+        return Err(DecodeError::BufferTooSmall { min, remaining });
+    }
+
+    let bytes = &buf[1..min];
+    *buf = &buf[min..];
+    Ok(bytes)
+}
+
+/// Reads a UTF-8 string prefixed by its length, on one byte, and advances `buf` past it.
+pub(crate) fn get_str_u8<'a>(
+    buf: &mut &'a [u8],
+    field: &'static str,
+) -> Result<&'a str, DecodeError> {
+    std::str::from_utf8(get_bytes_u8(buf)?).map_err(|_| DecodeError::InvalidUtf8 { field })
+}
+
+/// Writes a room code in its canonical form, prefixed by its length.
+pub(crate) fn put_code<B>(buf: &mut B, code: &Code) -> Result<(), EncodeError>
+where
+    B: BufMut,
+{
+    put_bytes_u8(buf, code.as_slice())
+}
+
+/// Reads a room code in its canonical form, and advances `buf` past it.
+pub(crate) fn get_code(buf: &mut &[u8]) -> Result<Code, DecodeError> {
+    Ok(get_bytes_u8(buf)?.try_into()?)
+}
+
+/// Reads a big-endian `u16`, and advances `buf` past it.
+pub(crate) fn get_u16(buf: &mut &[u8]) -> Result<u16, DecodeError> {
+    let remaining = buf.len();
+    if remaining < 2 {
+        return Err(DecodeError::BufferTooSmall { min: 2, remaining });
+    }
+
+    let value = u16::from_be_bytes([buf[0], buf[1]]);
+    *buf = &buf[2..];
+    Ok(value)
+}
+
+/// Reads a big-endian `u32`, and advances `buf` past it.
+pub(crate) fn get_u32(buf: &mut &[u8]) -> Result<u32, DecodeError> {
+    let remaining = buf.len();
+    if remaining < 4 {
+        return Err(DecodeError::BufferTooSmall { min: 4, remaining });
+    }
+
+    let value = u32::from_be_bytes(buf[..4].try_into().unwrap());
+    *buf = &buf[4..];
+    Ok(value)
+}
+
+/// Writes bytes prefixed by their length, on two bytes.
+pub(crate) fn put_bytes_u16<B>(buf: &mut B, bytes: &[u8]) -> Result<(), EncodeError>
+where
+    B: BufMut,
+{
+    let len = bytes.len();
+    if len > u16::MAX as usize {
+        return Err(EncodeError::TooLong {
+            max: u16::MAX as usize,
+            len,
+        });
+    }
+
+    buf.put_u16(len as u16);
+    buf.put_slice(bytes);
+    Ok(())
+}
+
+/// Reads bytes prefixed by their length, on two bytes, and advances `buf` past them.
+pub(crate) fn get_bytes_u16<'a>(buf: &mut &'a [u8]) -> Result<&'a [u8], DecodeError> {
+    let remaining = buf.len();
+    if remaining < 2 {
+        return Err(DecodeError::BufferTooSmall { min: 2, remaining });
+    }
+
+    let min = u16::from_be_bytes([buf[0], buf[1]]) as usize + 2;
+    if remaining < min {
+        return Err(DecodeError::BufferTooSmall { min, remaining });
+    }
+
+    let bytes = &buf[2..min];
+    *buf = &buf[min..];
+    Ok(bytes)
+}
+
+/// Writes a list of items prefixed by their count, on two bytes.
+pub(crate) fn encode_list<B, T, F>(buf: &mut B, items: &[T], encode: F) -> Result<(), EncodeError>
+where
+    B: BufMut,
+    F: Fn(&T, &mut B) -> Result<(), EncodeError>,
+{
+    let len = items.len();
+    if len > u16::MAX as usize {
+        return Err(EncodeError::TooLong {
+            max: u16::MAX as usize,
+            len,
+        });
+    }
+
+    buf.put_u16(len as u16);
+    for item in items {
+        encode(item, buf)?;
+    }
+    Ok(())
+}
+
+/// Reads a list of items prefixed by their count, on two bytes, and advances `buf` past them.
+pub(crate) fn decode_list<'a, T, F>(buf: &mut &'a [u8], decode: F) -> Result<Vec<T>, DecodeError>
+where
+    F: Fn(&mut &'a [u8]) -> Result<T, DecodeError>,
+{
+    let remaining = buf.len();
+    if remaining < 2 {
+        return Err(DecodeError::BufferTooSmall { min: 2, remaining });
+    }
+
+    let len = u16::from_be_bytes([buf[0], buf[1]]);
+    *buf = &buf[2..];
+
+    (0..len).map(|_| decode(buf)).collect()
+}
@@ -1,9 +1,13 @@
 use thiserror::Error;
 
+use crate::code::CodeError;
+
 #[derive(Error, Debug)]
 pub enum EncodeError {
     #[error("insufficient capacity (required: {required:?}, remaining: {remaining:?})")]
     InsufficientCapacity { required: usize, remaining: usize },
+    #[error("value too long (max: {max:?}, len: {len:?})")]
+    TooLong { max: usize, len: usize },
 }
 
 #[derive(Error, Debug)]
@@ -12,4 +16,23 @@ pub enum DecodeError {
     BufferTooSmall { min: usize, remaining: usize },
     #[error("bad message code {code:?}")]
     BadMessageCode { code: u8 },
+    #[error("bad value {value:?} for {field}")]
+    BadValue { field: &'static str, value: u8 },
+    #[error("invalid UTF-8 in {field}")]
+    InvalidUtf8 { field: &'static str },
+    #[error("invalid room code: {0}")]
+    InvalidCode(#[from] CodeError),
+}
+
+impl DecodeError {
+    /// Stable numeric code sent to clients in [`s2c::Message::Error`](crate::proto::s2c::Message).
+    pub const fn code(&self) -> u16 {
+        match self {
+            DecodeError::BufferTooSmall { .. } => 1,
+            DecodeError::BadMessageCode { .. } => 2,
+            DecodeError::BadValue { .. } => 3,
+            DecodeError::InvalidUtf8 { .. } => 4,
+            DecodeError::InvalidCode(_) => 5,
+        }
+    }
 }
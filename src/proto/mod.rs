@@ -1,6 +1,18 @@
 pub use forward::ForwardMessage;
+pub use version::{Capabilities, PROTOCOL_VERSION};
 
 pub mod c2s;
+mod codec;
 pub mod error;
 mod forward;
 pub mod s2c;
+pub mod version;
+
+/// Identifier chosen by a client to match replies with its c2s messages.
+pub type RequestId = u32;
+
+pub const RESUME_TOKEN_LEN: usize = 16;
+
+/// Secret given to a client along with its session id, allowing it to resume its session after a
+/// disconnection.
+pub type ResumeToken = [u8; RESUME_TOKEN_LEN];
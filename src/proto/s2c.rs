@@ -2,15 +2,309 @@ use bytes::BufMut;
 use uuid::Uuid;
 
 use crate::code::Code;
+use crate::proto::codec::{
+    decode_header, decode_list, decode_uuid, encode_header, encode_list, encode_uuid,
+    get_bytes_u16, get_bytes_u8, get_code, put_bytes_u16, put_bytes_u8, put_code, UUID_LEN,
+};
 use crate::proto::error::{DecodeError, EncodeError};
-use crate::proto::ForwardMessage;
+use crate::proto::{Capabilities, ForwardMessage, RequestId, ResumeToken, RESUME_TOKEN_LEN};
+
+/// Set in the optional fields of `RoomJoined` and `PlayerJoined` when player data is sent.
+const DATA_FIELD: u8 = 1 << 0;
+
+/// Set in the optional fields of `RoomJoined` and `PlayerJoined` when member roles are sent.
+const ROLE_FIELD: u8 = 1 << 1;
 
 pub enum Message<'a> {
     ReceiveFromPlayer(ForwardMessage<'a>),
-    AssignSessionId { session_id: &'a Uuid },
-    RoomCreated { code: &'a Code },
-    RoomJoined { host_session_id: &'a Uuid },
-    PlayerJoined { player_session_id: &'a Uuid },
+    /// Session id of the connection, with its resume token if the `SESSION_RESUME` capability was
+    /// negotiated.
+    AssignSessionId {
+        session_id: &'a Uuid,
+        resume_token: Option<&'a ResumeToken>,
+    },
+    RoomCreated {
+        code: Code,
+        request_id: Option<RequestId>,
+    },
+    /// Reply to `JoinRoom`. The members of the room are only sent to the clients having the
+    /// `ROSTER` capability, their data to the clients having the `PLAYER_DATA` capability too,
+    /// and their role to the clients having the `SPECTATORS` capability too.
+    RoomJoined {
+        host_session_id: &'a Uuid,
+        members: Option<Vec<MemberInfo<'a>>>,
+        request_id: Option<RequestId>,
+    },
+    /// The data of the player is only sent to the clients having the `PLAYER_DATA` capability,
+    /// and its role to the clients having the `SPECTATORS` capability.
+    PlayerJoined {
+        player_session_id: &'a Uuid,
+        data: Option<Vec<PlayerDataEntry<'a>>>,
+        role: Option<MemberRole>,
+    },
+    PlayerLeft {
+        player_session_id: &'a Uuid,
+    },
+    RoomClosed {
+        code: Code,
+        reason: RoomCloseReason,
+    },
+    HostChanged {
+        host_session_id: &'a Uuid,
+    },
+    /// Reply to a c2s message that could not be processed.
+    ///
+    /// `request_type` is the type code of the failed c2s message, or `0` if it could not be read.
+    Error {
+        code: u16,
+        request_type: u8,
+        request_id: Option<RequestId>,
+    },
+    /// Reply to a c2s message tagged with a request id, which has no other reply.
+    Ack {
+        request_id: RequestId,
+    },
+    /// Reply to the c2s `Hello`, with the protocol version and capabilities used from now on.
+    Hello {
+        protocol_version: u16,
+        capabilities: Capabilities,
+    },
+    /// Reply to `ListRooms`.
+    RoomList {
+        rooms: Vec<RoomInfo<'a>>,
+        request_id: Option<RequestId>,
+    },
+    /// Sent to a player removed from its room by the host.
+    Kicked {
+        reason: &'a [u8],
+    },
+    /// Sent when a value of the room is set, or deleted if `value` is `None`, and for every value
+    /// of the room when joining it.
+    RoomValueChanged {
+        key: &'a [u8],
+        value: Option<&'a [u8]>,
+    },
+    /// Sent when a member of the room sets one of its data entries, or deletes it if `value` is
+    /// `None`.
+    PlayerDataChanged {
+        player_session_id: &'a Uuid,
+        key: &'a [u8],
+        value: Option<&'a [u8]>,
+    },
+    /// Sent when a member of the room becomes ready or not, and for every ready member when
+    /// joining the room.
+    PlayerReadyChanged {
+        player_session_id: &'a Uuid,
+        ready: bool,
+    },
+    /// Sent when the host starts the game, which begins at `start_at`, in milliseconds since the
+    /// Unix epoch. The ready flags of every member are cleared.
+    GameStarting {
+        start_at: u64,
+    },
+    /// Sent when the phase of the room changes for another reason than the host starting the
+    /// game, and when joining a room outside of its lobby.
+    RoomPhaseChanged {
+        phase: RoomPhase,
+    },
+    /// Sent to the players waiting in a matchmaking queue whenever it changes. `position` starts
+    /// at 1.
+    QueuePosition {
+        position: u16,
+        waiting: u16,
+    },
+    /// A `ReceiveFromPlayer` broadcast before the player joined the room, replayed from its
+    /// history. The history is sent right after `RoomJoined` and the state of the room, oldest
+    /// payload first, before any live message.
+    HistoryFromPlayer(ForwardMessage<'a>),
+}
+
+/// Member of a room, as listed in `RoomJoined`.
+///
+/// `data` and `role` are each sent either for every member or for none of them.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MemberInfo<'a> {
+    pub session_id: &'a Uuid,
+    pub data: Option<Vec<PlayerDataEntry<'a>>>,
+    pub role: Option<MemberRole>,
+}
+
+impl<'a> MemberInfo<'a> {
+    fn encode<B>(&self, buf: &mut B) -> Result<(), EncodeError>
+    where
+        B: BufMut,
+    {
+        encode_uuid(buf, self.session_id)
+    }
+
+    fn decode(buf: &mut &'a [u8]) -> Result<Self, DecodeError> {
+        let remaining = buf.len();
+        if remaining < UUID_LEN {
+            return Err(DecodeError::BufferTooSmall {
+                min: UUID_LEN,
+                remaining,
+            });
+        }
+
+        let session_id = decode_uuid(&buf[..UUID_LEN])?;
+        *buf = &buf[UUID_LEN..];
+
+        Ok(MemberInfo {
+            session_id,
+            data: None,
+            role: None,
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[repr(u8)]
+pub enum MemberRole {
+    #[default]
+    Player = 0,
+    /// Receives the messages sent to the room, but can't send any to its players, and doesn't
+    /// count toward its capacity.
+    Spectator = 1,
+}
+
+impl TryFrom<u8> for MemberRole {
+    type Error = DecodeError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(MemberRole::Player),
+            1 => Ok(MemberRole::Spectator),
+            v => Err(DecodeError::BadValue {
+                field: "role",
+                value: v,
+            }),
+        }
+    }
+}
+
+/// Entry of the custom data a player attaches to itself.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct PlayerDataEntry<'a> {
+    pub key: &'a [u8],
+    pub value: &'a [u8],
+}
+
+impl<'a> PlayerDataEntry<'a> {
+    fn encode<B>(&self, buf: &mut B) -> Result<(), EncodeError>
+    where
+        B: BufMut,
+    {
+        put_bytes_u8(buf, self.key)?;
+        put_bytes_u16(buf, self.value)
+    }
+
+    fn decode(buf: &mut &'a [u8]) -> Result<Self, DecodeError> {
+        Ok(PlayerDataEntry {
+            key: get_bytes_u8(buf)?,
+            value: get_bytes_u16(buf)?,
+        })
+    }
+}
+
+/// Public description of a room, as listed in `RoomList`.
+#[derive(Copy, Clone)]
+pub struct RoomInfo<'a> {
+    pub code: Code,
+    pub player_count: u16,
+    /// `0` for no limit.
+    pub max_players: u8,
+    pub has_password: bool,
+    pub metadata: &'a [u8],
+}
+
+impl<'a> RoomInfo<'a> {
+    fn encode<B>(&self, buf: &mut B) -> Result<(), EncodeError>
+    where
+        B: BufMut,
+    {
+        put_code(buf, &self.code)?;
+        buf.put_u16(self.player_count);
+        buf.put_u8(self.max_players);
+        buf.put_u8(self.has_password as u8);
+        put_bytes_u16(buf, self.metadata)
+    }
+
+    fn decode(buf: &mut &'a [u8]) -> Result<Self, DecodeError> {
+        const MIN_LEN: usize = 4;
+
+        let code = get_code(buf)?;
+
+        let remaining = buf.len();
+        if remaining < MIN_LEN {
+            return Err(DecodeError::BufferTooSmall {
+                min: MIN_LEN,
+                remaining,
+            });
+        }
+
+        let player_count = u16::from_be_bytes([buf[0], buf[1]]);
+        let max_players = buf[2];
+        let has_password = buf[3] != 0;
+        *buf = &buf[MIN_LEN..];
+
+        Ok(RoomInfo {
+            code,
+            player_count,
+            max_players,
+            has_password,
+            metadata: get_bytes_u16(buf)?,
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[repr(u8)]
+pub enum RoomCloseReason {
+    HostLeft = 1,
+}
+
+impl TryFrom<u8> for RoomCloseReason {
+    type Error = DecodeError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(RoomCloseReason::HostLeft),
+            v => Err(DecodeError::BadValue {
+                field: "reason",
+                value: v,
+            }),
+        }
+    }
+}
+
+/// Progress of the game played in a room.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[repr(u8)]
+pub enum RoomPhase {
+    /// The members are getting ready.
+    #[default]
+    Lobby = 0,
+    /// The game was started and begins at the end of the countdown.
+    Starting = 1,
+    InGame = 2,
+    Finished = 3,
+}
+
+impl TryFrom<u8> for RoomPhase {
+    type Error = DecodeError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(RoomPhase::Lobby),
+            1 => Ok(RoomPhase::Starting),
+            2 => Ok(RoomPhase::InGame),
+            3 => Ok(RoomPhase::Finished),
+            v => Err(DecodeError::BadValue {
+                field: "phase",
+                value: v,
+            }),
+        }
+    }
 }
 
 impl<'a> Message<'a> {
@@ -18,40 +312,434 @@ impl<'a> Message<'a> {
     where
         B: BufMut,
     {
-        buf.put_u8(self.type_code());
+        encode_header(buf, self.type_code(), self.request_id());
 
         match self {
-            Message::ReceiveFromPlayer(fwd) => fwd.encode(buf),
-            Message::AssignSessionId { session_id } => encode_uuid(buf, session_id),
-            Message::RoomCreated { code } => Ok(buf.put_slice(code.as_slice())),
-            Message::RoomJoined { host_session_id } => encode_uuid(buf, host_session_id),
-            Message::PlayerJoined { player_session_id } => encode_uuid(buf, player_session_id),
+            Message::ReceiveFromPlayer(fwd) | Message::HistoryFromPlayer(fwd) => fwd.encode(buf),
+            Message::AssignSessionId {
+                session_id,
+                resume_token,
+            } => {
+                encode_uuid(buf, session_id)?;
+                if let Some(resume_token) = resume_token {
+                    buf.put_slice(*resume_token);
+                }
+                Ok(())
+            }
+            Message::RoomCreated { code, .. } => put_code(buf, code),
+            Message::RoomJoined {
+                host_session_id,
+                members,
+                ..
+            } => {
+                encode_uuid(buf, host_session_id)?;
+                let members = match members {
+                    Some(members) => members,
+                    None => return Ok(()),
+                };
+                encode_list(buf, members, MemberInfo::encode)?;
+
+                // The optional fields of the members follow the list, in the same order
+                let mut fields = 0;
+                if !members.is_empty() && members.iter().all(|member| member.data.is_some()) {
+                    fields |= DATA_FIELD;
+                }
+                if !members.is_empty() && members.iter().all(|member| member.role.is_some()) {
+                    fields |= ROLE_FIELD;
+                }
+                if fields == 0 {
+                    return Ok(());
+                }
+
+                buf.put_u8(fields);
+                if fields & DATA_FIELD != 0 {
+                    for member in members {
+                        encode_list(
+                            buf,
+                            member.data.as_deref().unwrap_or_default(),
+                            PlayerDataEntry::encode,
+                        )?;
+                    }
+                }
+                if fields & ROLE_FIELD != 0 {
+                    for member in members {
+                        buf.put_u8(member.role.unwrap_or_default() as u8);
+                    }
+                }
+                Ok(())
+            }
+            Message::PlayerJoined {
+                player_session_id,
+                data,
+                role,
+            } => {
+                encode_uuid(buf, player_session_id)?;
+
+                let fields =
+                    data.as_ref().map_or(0, |_| DATA_FIELD) | role.map_or(0, |_| ROLE_FIELD);
+                if fields == 0 {
+                    return Ok(());
+                }
+
+                buf.put_u8(fields);
+                if let Some(data) = data {
+                    encode_list(buf, data, PlayerDataEntry::encode)?;
+                }
+                if let Some(role) = role {
+                    buf.put_u8(*role as u8);
+                }
+                Ok(())
+            }
+            Message::PlayerLeft { player_session_id } => encode_uuid(buf, player_session_id),
+            Message::RoomClosed { code, reason } => {
+                put_code(buf, code)?;
+                buf.put_u8(*reason as u8);
+                Ok(())
+            }
+            Message::HostChanged { host_session_id } => encode_uuid(buf, host_session_id),
+            Message::Error {
+                code, request_type, ..
+            } => {
+                buf.put_u16(*code);
+                buf.put_u8(*request_type);
+                Ok(())
+            }
+            Message::Ack { request_id } => {
+                buf.put_u32(*request_id);
+                Ok(())
+            }
+            Message::Hello {
+                protocol_version,
+                capabilities,
+            } => {
+                buf.put_u16(*protocol_version);
+                buf.put_u32(capabilities.bits());
+                Ok(())
+            }
+            Message::RoomList { rooms, .. } => encode_list(buf, rooms, RoomInfo::encode),
+            Message::Kicked { reason } => {
+                buf.put_slice(reason);
+                Ok(())
+            }
+            Message::RoomValueChanged { key, value } => {
+                put_bytes_u8(buf, key)?;
+                buf.put_u8(value.is_some() as u8);
+                buf.put_slice(value.unwrap_or_default());
+                Ok(())
+            }
+            Message::PlayerDataChanged {
+                player_session_id,
+                key,
+                value,
+            } => {
+                encode_uuid(buf, player_session_id)?;
+                put_bytes_u8(buf, key)?;
+                buf.put_u8(value.is_some() as u8);
+                buf.put_slice(value.unwrap_or_default());
+                Ok(())
+            }
+            Message::PlayerReadyChanged {
+                player_session_id,
+                ready,
+            } => {
+                encode_uuid(buf, player_session_id)?;
+                buf.put_u8(*ready as u8);
+                Ok(())
+            }
+            Message::GameStarting { start_at } => {
+                buf.put_u64(*start_at);
+                Ok(())
+            }
+            Message::RoomPhaseChanged { phase } => {
+                buf.put_u8(*phase as u8);
+                Ok(())
+            }
+            Message::QueuePosition { position, waiting } => {
+                buf.put_u16(*position);
+                buf.put_u16(*waiting);
+                Ok(())
+            }
         }
     }
 
     pub fn decode(buf: &'a [u8]) -> Result<Self, DecodeError> {
-        //let remaining = buf.len();
-
-        match buf[0] {
-            1 => Ok(Message::ReceiveFromPlayer(ForwardMessage::decode(
-                &buf[1..],
-            )?)),
-            2 => Ok(Message::AssignSessionId {
-                session_id: decode_uuid(&buf[1..17])?,
-            }),
-            3 => Ok(Message::RoomCreated {
-                code: buf[1..5].try_into().unwrap(),
-            }),
-            4 => Ok(Message::RoomJoined {
-                host_session_id: decode_uuid(&buf[1..17])?,
+        let (type_code, request_id, body) = decode_header(buf)?;
+
+        match type_code {
+            1 => Ok(Message::ReceiveFromPlayer(ForwardMessage::decode(body)?)),
+            2 => {
+                let remaining = body.len();
+                let resume_token = match remaining {
+                    UUID_LEN => None,
+                    len if len == UUID_LEN + RESUME_TOKEN_LEN => {
+                        Some(body[UUID_LEN..].try_into().unwrap())
+                    }
+                    _ => {
+                        return Err(DecodeError::BufferTooSmall {
+                            min: UUID_LEN,
+                            remaining,
+                        })
+                    }
+                };
+
+                Ok(Message::AssignSessionId {
+                    session_id: decode_uuid(&body[..UUID_LEN])?,
+                    resume_token,
+                })
+            }
+            3 => {
+                let mut body = body;
+
+                Ok(Message::RoomCreated {
+                    code: get_code(&mut body)?,
+                    request_id,
+                })
+            }
+            4 => {
+                let remaining = body.len();
+                if remaining < UUID_LEN {
+                    return Err(DecodeError::BufferTooSmall {
+                        min: UUID_LEN,
+                        remaining,
+                    });
+                }
+
+                let mut rest = &body[UUID_LEN..];
+                let members = if rest.is_empty() {
+                    None
+                } else {
+                    let mut members = decode_list(&mut rest, MemberInfo::decode)?;
+
+                    let fields = get_fields(&mut rest)?;
+                    if fields & DATA_FIELD != 0 {
+                        for member in &mut members {
+                            member.data = Some(decode_list(&mut rest, PlayerDataEntry::decode)?);
+                        }
+                    }
+                    if fields & ROLE_FIELD != 0 {
+                        for member in &mut members {
+                            member.role = Some(get_role(&mut rest)?);
+                        }
+                    }
+                    Some(members)
+                };
+
+                Ok(Message::RoomJoined {
+                    host_session_id: decode_uuid(&body[..UUID_LEN])?,
+                    members,
+                    request_id,
+                })
+            }
+            5 => {
+                let remaining = body.len();
+                if remaining < UUID_LEN {
+                    return Err(DecodeError::BufferTooSmall {
+                        min: UUID_LEN,
+                        remaining,
+                    });
+                }
+
+                let mut rest = &body[UUID_LEN..];
+                let fields = get_fields(&mut rest)?;
+
+                let data = if fields & DATA_FIELD != 0 {
+                    Some(decode_list(&mut rest, PlayerDataEntry::decode)?)
+                } else {
+                    None
+                };
+                let role = if fields & ROLE_FIELD != 0 {
+                    Some(get_role(&mut rest)?)
+                } else {
+                    None
+                };
+
+                Ok(Message::PlayerJoined {
+                    player_session_id: decode_uuid(&body[..UUID_LEN])?,
+                    data,
+                    role,
+                })
+            }
+            6 => Ok(Message::PlayerLeft {
+                player_session_id: decode_uuid(body)?,
             }),
-            5 => Ok(Message::PlayerJoined {
-                player_session_id: decode_uuid(&buf[1..17])?,
+            7 => {
+                let mut body = body;
+                let code = get_code(&mut body)?;
+
+                let remaining = body.len();
+                if remaining < 1 {
+                    return Err(DecodeError::BufferTooSmall { min: 1, remaining });
+                }
+
+                Ok(Message::RoomClosed {
+                    code,
+                    reason: body[0].try_into()?,
+                })
+            }
+            8 => Ok(Message::HostChanged {
+                host_session_id: decode_uuid(body)?,
             }),
+            9 => {
+                let remaining = body.len();
+                if remaining < 3 {
+                    return Err(DecodeError::BufferTooSmall { min: 3, remaining });
+                }
+
+                Ok(Message::Error {
+                    code: u16::from_be_bytes([body[0], body[1]]),
+                    request_type: body[2],
+                    request_id,
+                })
+            }
+            10 => {
+                let remaining = body.len();
+                if remaining < 4 {
+                    return Err(DecodeError::BufferTooSmall { min: 4, remaining });
+                }
+
+                Ok(Message::Ack {
+                    request_id: RequestId::from_be_bytes(body[..4].try_into().unwrap()),
+                })
+            }
+            11 => {
+                let remaining = body.len();
+                if remaining < 6 {
+                    return Err(DecodeError::BufferTooSmall { min: 6, remaining });
+                }
+
+                Ok(Message::Hello {
+                    protocol_version: u16::from_be_bytes(body[..2].try_into().unwrap()),
+                    capabilities: Capabilities::from_bits(u32::from_be_bytes(
+                        body[2..6].try_into().unwrap(),
+                    )),
+                })
+            }
+            12 => {
+                let mut body = body;
+
+                Ok(Message::RoomList {
+                    rooms: decode_list(&mut body, RoomInfo::decode)?,
+                    request_id,
+                })
+            }
+            13 => Ok(Message::Kicked { reason: body }),
+            14 => {
+                let mut rest = body;
+                let key = get_bytes_u8(&mut rest)?;
+
+                let remaining = rest.len();
+                if remaining < 1 {
+                    return Err(DecodeError::BufferTooSmall { min: 1, remaining });
+                }
+
+                Ok(Message::RoomValueChanged {
+                    key,
+                    value: (rest[0] != 0).then_some(&rest[1..]),
+                })
+            }
+            15 => {
+                let remaining = body.len();
+                if remaining < UUID_LEN {
+                    return Err(DecodeError::BufferTooSmall {
+                        min: UUID_LEN,
+                        remaining,
+                    });
+                }
+
+                let mut rest = &body[UUID_LEN..];
+                let key = get_bytes_u8(&mut rest)?;
+
+                let remaining = rest.len();
+                if remaining < 1 {
+                    return Err(DecodeError::BufferTooSmall { min: 1, remaining });
+                }
+
+                Ok(Message::PlayerDataChanged {
+                    player_session_id: decode_uuid(&body[..UUID_LEN])?,
+                    key,
+                    value: (rest[0] != 0).then_some(&rest[1..]),
+                })
+            }
+            16 => {
+                let remaining = body.len();
+                if remaining < UUID_LEN + 1 {
+                    return Err(DecodeError::BufferTooSmall {
+                        min: UUID_LEN + 1,
+                        remaining,
+                    });
+                }
+
+                Ok(Message::PlayerReadyChanged {
+                    player_session_id: decode_uuid(&body[..UUID_LEN])?,
+                    ready: body[UUID_LEN] != 0,
+                })
+            }
+            17 => {
+                let remaining = body.len();
+                if remaining < 8 {
+                    return Err(DecodeError::BufferTooSmall { min: 8, remaining });
+                }
+
+                Ok(Message::GameStarting {
+                    start_at: u64::from_be_bytes(body[..8].try_into().unwrap()),
+                })
+            }
+            18 => {
+                let remaining = body.len();
+                if remaining < 1 {
+                    return Err(DecodeError::BufferTooSmall { min: 1, remaining });
+                }
+
+                Ok(Message::RoomPhaseChanged {
+                    phase: body[0].try_into()?,
+                })
+            }
+            19 => {
+                let remaining = body.len();
+                if remaining < 4 {
+                    return Err(DecodeError::BufferTooSmall { min: 4, remaining });
+                }
+
+                Ok(Message::QueuePosition {
+                    position: u16::from_be_bytes([body[0], body[1]]),
+                    waiting: u16::from_be_bytes([body[2], body[3]]),
+                })
+            }
+            20 => Ok(Message::HistoryFromPlayer(ForwardMessage::decode(body)?)),
             c => Err(DecodeError::BadMessageCode { code: c }),
         }
     }
 
+    /// Capabilities a connection must have negotiated to receive this message.
+    pub const fn required_capabilities(&self) -> Capabilities {
+        match self {
+            Message::PlayerLeft { .. }
+            | Message::RoomClosed { .. }
+            | Message::HostChanged { .. }
+            | Message::Kicked { .. } => Capabilities::ROOM_EVENTS,
+            Message::Error { .. } => Capabilities::ERROR_REPLIES,
+            Message::RoomValueChanged { .. } => Capabilities::ROOM_STATE,
+            Message::PlayerDataChanged { .. } => Capabilities::PLAYER_DATA,
+            Message::PlayerReadyChanged { .. }
+            | Message::GameStarting { .. }
+            | Message::RoomPhaseChanged { .. } => Capabilities::LOBBY,
+            Message::QueuePosition { .. } => Capabilities::MATCHMAKING,
+            Message::HistoryFromPlayer(_) => Capabilities::HISTORY,
+            _ => Capabilities::NONE,
+        }
+    }
+
+    /// Request id of the c2s message this message replies to, if it is carried in the header.
+    const fn request_id(&self) -> Option<RequestId> {
+        match self {
+            Message::RoomCreated { request_id, .. }
+            | Message::RoomJoined { request_id, .. }
+            | Message::Error { request_id, .. }
+            | Message::RoomList { request_id, .. } => *request_id,
+            _ => None,
+        }
+    }
+
     const fn type_code(&self) -> u8 {
         match self {
             Message::ReceiveFromPlayer(_) => 1,
@@ -59,35 +747,189 @@ impl<'a> Message<'a> {
             Message::RoomCreated { .. } => 3,
             Message::RoomJoined { .. } => 4,
             Message::PlayerJoined { .. } => 5,
+            Message::PlayerLeft { .. } => 6,
+            Message::RoomClosed { .. } => 7,
+            Message::HostChanged { .. } => 8,
+            Message::Error { .. } => 9,
+            Message::Ack { .. } => 10,
+            Message::Hello { .. } => 11,
+            Message::RoomList { .. } => 12,
+            Message::Kicked { .. } => 13,
+            Message::RoomValueChanged { .. } => 14,
+            Message::PlayerDataChanged { .. } => 15,
+            Message::PlayerReadyChanged { .. } => 16,
+            Message::GameStarting { .. } => 17,
+            Message::RoomPhaseChanged { .. } => 18,
+            Message::QueuePosition { .. } => 19,
+            Message::HistoryFromPlayer(_) => 20,
         }
     }
 }
 
-fn encode_uuid<B>(buf: &mut B, uuid: &Uuid) -> Result<(), EncodeError>
-where
-    B: BufMut,
-{
-    let remaining = buf.remaining_mut();
-    if remaining < 16 {
-        return Err(EncodeError::InsufficientCapacity {
-            required: 16,
-            remaining,
-        });
+/// Reads the optional fields following a message, `0` if there are none.
+fn get_fields(buf: &mut &[u8]) -> Result<u8, DecodeError> {
+    match buf.split_first() {
+        Some((&fields, rest)) => {
+            *buf = rest;
+            Ok(fields)
+        }
+        None => Ok(0),
     }
+}
 
-    buf.put_slice(uuid.as_bytes());
-    Ok(())
+fn get_role(buf: &mut &[u8]) -> Result<MemberRole, DecodeError> {
+    match buf.split_first() {
+        Some((&role, rest)) => {
+            *buf = rest;
+            role.try_into()
+        }
+        None => Err(DecodeError::BufferTooSmall {
+            min: 1,
+            remaining: 0,
+        }),
+    }
 }
 
-fn decode_uuid(buf: &[u8]) -> Result<&Uuid, DecodeError> {
-    let len = buf.len();
-    if len != 16 {
-        return Err(DecodeError::BufferTooSmall {
-            remaining: len,
-            min: 16,
-        });
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_round_trip() {
+        let mut buf = vec![];
+        Message::Error {
+            code: 0x0102,
+            request_type: 3,
+            request_id: None,
+        }
+        .encode(&mut buf)
+        .unwrap();
+
+        assert_eq!(vec![9, 1, 2, 3], buf);
+
+        match Message::decode(&buf).unwrap() {
+            Message::Error {
+                code,
+                request_type,
+                request_id,
+            } => {
+                assert_eq!(0x0102, code);
+                assert_eq!(3, request_type);
+                assert_eq!(None, request_id);
+            }
+            _ => panic!("decoded the wrong message"),
+        }
     }
 
-    let b: &[u8; 16] = buf[0..16].try_into().unwrap();
-    Ok(Uuid::from_bytes_ref(&b))
+    #[test]
+    fn request_id_round_trip() {
+        let mut buf = vec![];
+        Message::Error {
+            code: 0x0102,
+            request_type: 3,
+            request_id: Some(42),
+        }
+        .encode(&mut buf)
+        .unwrap();
+
+        assert_eq!(vec![0x89, 0, 0, 0, 42, 1, 2, 3], buf);
+
+        match Message::decode(&buf).unwrap() {
+            Message::Error { request_id, .. } => assert_eq!(Some(42), request_id),
+            _ => panic!("decoded the wrong message"),
+        }
+    }
+
+    #[test]
+    fn room_list_round_trip() {
+        let code = Code::try_from(&b"K7M2QX"[..]).unwrap();
+
+        let mut buf = vec![];
+        Message::RoomList {
+            rooms: vec![RoomInfo {
+                code,
+                player_count: 3,
+                max_players: 4,
+                has_password: true,
+                metadata: b"deathmatch",
+            }],
+            request_id: Some(7),
+        }
+        .encode(&mut buf)
+        .unwrap();
+
+        match Message::decode(&buf).unwrap() {
+            Message::RoomList { rooms, request_id } => {
+                assert_eq!(Some(7), request_id);
+                assert_eq!(1, rooms.len());
+                assert_eq!(code, rooms[0].code);
+                assert_eq!(3, rooms[0].player_count);
+                assert_eq!(4, rooms[0].max_players);
+                assert!(rooms[0].has_password);
+                assert_eq!(b"deathmatch", rooms[0].metadata);
+            }
+            _ => panic!("decoded the wrong message"),
+        }
+    }
+
+    #[test]
+    fn room_value_changed_round_trip() {
+        for value in [Some(&b""[..]), Some(b"red"), None] {
+            let mut buf = vec![];
+            Message::RoomValueChanged {
+                key: b"team",
+                value,
+            }
+            .encode(&mut buf)
+            .unwrap();
+
+            match Message::decode(&buf).unwrap() {
+                Message::RoomValueChanged {
+                    key,
+                    value: decoded,
+                } => {
+                    assert_eq!(b"team", key);
+                    assert_eq!(value, decoded);
+                }
+                _ => panic!("decoded the wrong message"),
+            }
+        }
+    }
+
+    #[test]
+    fn room_joined_with_member_fields_round_trip() {
+        let ids = [Uuid::new_v4(), Uuid::new_v4()];
+        let nickname = PlayerDataEntry {
+            key: b"nickname",
+            value: b"ada",
+        };
+        let members = vec![
+            MemberInfo {
+                session_id: &ids[0],
+                data: Some(vec![nickname]),
+                role: Some(MemberRole::Player),
+            },
+            MemberInfo {
+                session_id: &ids[1],
+                data: Some(vec![]),
+                role: Some(MemberRole::Spectator),
+            },
+        ];
+
+        let mut buf = vec![];
+        Message::RoomJoined {
+            host_session_id: &ids[0],
+            members: Some(members.clone()),
+            request_id: None,
+        }
+        .encode(&mut buf)
+        .unwrap();
+
+        match Message::decode(&buf).unwrap() {
+            Message::RoomJoined {
+                members: decoded, ..
+            } => assert_eq!(Some(members), decoded),
+            _ => panic!("decoded the wrong message"),
+        }
+    }
 }
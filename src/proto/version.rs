@@ -0,0 +1,104 @@
+use std::ops::RangeInclusive;
+
+/// Version of the protocol spoken by this server.
+pub const PROTOCOL_VERSION: u16 = 1;
+
+/// Oldest protocol version still accepted from clients.
+pub const MIN_PROTOCOL_VERSION: u16 = 1;
+
+pub const SUPPORTED_PROTOCOL_VERSIONS: RangeInclusive<u16> =
+    MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION;
+
+/// Set of optional protocol features, negotiated per connection during the `Hello` exchange.
+///
+/// Unsolicited s2c messages introduced after the first protocol version require a capability, so
+/// clients never receive messages they don't know about.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Capabilities(u32);
+
+impl Capabilities {
+    pub const NONE: Self = Self(0);
+    /// `PlayerLeft`, `RoomClosed`, `HostChanged` and `Kicked` notifications.
+    pub const ROOM_EVENTS: Self = Self(1 << 0);
+    /// `Error` replies to the c2s messages that could not be processed.
+    pub const ERROR_REPLIES: Self = Self(1 << 1);
+    /// Resume tokens in `AssignSessionId`, to resume the session after a disconnection.
+    pub const SESSION_RESUME: Self = Self(1 << 2);
+    /// Members of the room in `RoomJoined`.
+    pub const ROSTER: Self = Self(1 << 3);
+    /// `RoomValueChanged` notifications, and the values of the room when joining it.
+    pub const ROOM_STATE: Self = Self(1 << 4);
+    /// Player data in `RoomJoined` and `PlayerJoined`, and `PlayerDataChanged` notifications.
+    pub const PLAYER_DATA: Self = Self(1 << 5);
+    /// `PlayerReadyChanged`, `GameStarting` and `RoomPhaseChanged` notifications.
+    pub const LOBBY: Self = Self(1 << 6);
+    /// `QueuePosition` notifications.
+    pub const MATCHMAKING: Self = Self(1 << 7);
+    /// Member roles in `RoomJoined` and `PlayerJoined`.
+    pub const SPECTATORS: Self = Self(1 << 8);
+    /// `HistoryFromPlayer` messages, replaying the history of a room when joining it.
+    pub const HISTORY: Self = Self(1 << 9);
+
+    /// Every capability supported by this server.
+    pub const ALL: Self = Self(
+        Self::ROOM_EVENTS.0
+            | Self::ERROR_REPLIES.0
+            | Self::SESSION_RESUME.0
+            | Self::ROSTER.0
+            | Self::ROOM_STATE.0
+            | Self::PLAYER_DATA.0
+            | Self::LOBBY.0
+            | Self::MATCHMAKING.0
+            | Self::SPECTATORS.0
+            | Self::HISTORY.0,
+    );
+
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(&self) -> u32 {
+        self.0
+    }
+
+    pub const fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn intersection(&self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+}
+
+/// Picks the protocol version and capabilities used with a client, given the ones it announced.
+///
+/// Returns `None` if the client's protocol version is too old.
+pub fn negotiate(protocol_version: u16, capabilities: Capabilities) -> Option<(u16, Capabilities)> {
+    if protocol_version < MIN_PROTOCOL_VERSION {
+        return None;
+    }
+
+    Some((
+        protocol_version.min(PROTOCOL_VERSION),
+        capabilities.intersection(Capabilities::ALL),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiate_downgrades_newer_clients() {
+        let (version, capabilities) =
+            negotiate(PROTOCOL_VERSION + 1, Capabilities::from_bits(u32::MAX)).unwrap();
+
+        assert_eq!(PROTOCOL_VERSION, version);
+        assert_eq!(Capabilities::ALL, capabilities);
+    }
+
+    #[test]
+    fn negotiate_rejects_old_clients() {
+        assert!(negotiate(MIN_PROTOCOL_VERSION - 1, Capabilities::ALL).is_none());
+    }
+}
@@ -1,10 +1,14 @@
 use std::convert::Infallible;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use futures_util::future::join_all;
+use futures_util::stream::SplitStream;
 use futures_util::{SinkExt, StreamExt, TryFutureExt};
+use thiserror::Error;
 use tokio::sync::{mpsc, RwLock};
+use tokio::time::{sleep, timeout};
 use tokio_stream::wrappers::UnboundedReceiverStream;
 use uuid::Uuid;
 use warp::http::header::CONTENT_TYPE;
@@ -13,18 +17,59 @@ use warp::ws::{WebSocket, Ws};
 use warp::{ws, Filter};
 
 use crate::app;
-use crate::app::error::ProcessError;
+use crate::app::error::{ProcessError, SendError};
 use crate::app::{App, Player, Room};
+use crate::proto::error::DecodeError;
+use crate::proto::version::{self, SUPPORTED_PROTOCOL_VERSIONS};
+use crate::proto::{c2s, s2c, Capabilities, ResumeToken};
 
 const APPLICATION_JSON: HeaderValue = HeaderValue::from_static("application/json");
 
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Websocket close code sent when the handshake fails.
+const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+
+/// Websocket close code sent to a connection whose session was resumed by another one.
+const CLOSE_SESSION_RESUMED: u16 = 4000;
+
+/// Outcome of a successful handshake.
+struct Handshake {
+    protocol_version: u16,
+    capabilities: Capabilities,
+    resume_token: Option<ResumeToken>,
+}
+
+#[derive(Error, Debug)]
+enum HandshakeError {
+    #[error("websocket error: {0}")]
+    Websocket(#[from] warp::Error),
+    #[error("connection closed before Hello")]
+    Closed,
+    #[error("no Hello received within {}s", HANDSHAKE_TIMEOUT.as_secs())]
+    Timeout,
+    #[error("invalid Hello: {0}")]
+    Decode(#[from] DecodeError),
+    #[error("expected Hello as first message")]
+    ExpectedHello,
+    #[error(
+        "unsupported protocol version {version} (supported: {}-{})",
+        SUPPORTED_PROTOCOL_VERSIONS.start(),
+        SUPPORTED_PROTOCOL_VERSIONS.end()
+    )]
+    UnsupportedVersion { version: u16 },
+}
+
 pub async fn run(listen_addr: &SocketAddr, app: Arc<RwLock<App>>) {
     let app = warp::any().map(move || app.clone());
 
     let netcode = warp::path("netcode")
         .and(warp::ws())
+        .and(warp::addr::remote())
         .and(app.clone())
-        .map(|ws: Ws, app| ws.on_upgrade(move |socket| player_connected(socket, app)));
+        .map(|ws: Ws, remote: Option<SocketAddr>, app| {
+            ws.on_upgrade(move |socket| player_connected(socket, remote, app))
+        });
 
     let ping = warp::path("ping").map(|| {
         Response::builder()
@@ -42,27 +87,44 @@ pub async fn run(listen_addr: &SocketAddr, app: Arc<RwLock<App>>) {
     warp::serve(routes).run(*listen_addr).await;
 }
 
-async fn player_connected(ws: WebSocket, app: Arc<RwLock<App>>) {
-    // Create the channel used to send S2C messages
-    let (tx_s2c, rx_s2c) = mpsc::unbounded_channel::<Vec<u8>>();
-    let mut rx_s2c = UnboundedReceiverStream::new(rx_s2c);
+async fn player_connected(ws: WebSocket, remote: Option<SocketAddr>, app: Arc<RwLock<App>>) {
+    // Split the socket into a write half and a read half
+    let (mut ws_tx, mut ws_rx) = ws.split();
 
-    // Create a Player associated with the connection
-    let (player, player_id) = match app.write().await.add_player(Player::new(tx_s2c)).await {
-        Ok(player) => {
-            let session_id = *player.read().await.get_session_id();
-            (player, session_id)
-        }
+    let handshake = match handshake(&mut ws_rx).await {
+        Ok(handshake) => handshake,
         Err(e) => {
-            eprintln!("failed to add player: {:?}", e);
+            eprintln!("handshake error: {}", e);
+            ws_tx
+                .send(ws::Message::close_with(CLOSE_PROTOCOL_ERROR, e.to_string()))
+                .unwrap_or_else(|e| eprintln!("websocket close error: {}", e))
+                .await;
             return;
         }
     };
 
-    // Split the socket into a write half and a read half
-    let (mut ws_tx, mut ws_rx) = ws.split();
+    // Create the channel used to send S2C messages
+    let (tx_s2c, rx_s2c) = mpsc::unbounded_channel::<Vec<u8>>();
+    let mut rx_s2c = UnboundedReceiverStream::new(rx_s2c);
+
+    // Create a Player associated with the connection, or resume a previous session
+    let (player, player_id, connection) =
+        match connect_player(&app, tx_s2c, remote, &handshake).await {
+            Ok(player) => {
+                let (session_id, connection) = {
+                    let player = player.read().await;
+                    (*player.get_session_id(), player.get_connection())
+                };
+                (player, session_id, connection)
+            }
+            Err(e) => {
+                eprintln!("failed to add player: {:?}", e);
+                return;
+            }
+        };
 
     // Task forwarding messages from rx_s2c to ws_tx
+    let weak_player = Arc::downgrade(&player);
     tokio::task::spawn(async move {
         while let Some(message) = rx_s2c.next().await {
             ws_tx
@@ -72,6 +134,20 @@ async fn player_connected(ws: WebSocket, app: Arc<RwLock<App>>) {
                 })
                 .await;
         }
+
+        // The channel closes once the session is over, or taken over by another connection
+        let Some(player) = weak_player.upgrade() else {
+            return;
+        };
+        if player.read().await.get_connection() != connection {
+            ws_tx
+                .send(ws::Message::close_with(
+                    CLOSE_SESSION_RESUMED,
+                    "session resumed by another connection",
+                ))
+                .unwrap_or_else(|e| eprintln!("websocket close error: {}", e))
+                .await;
+        }
     });
 
     // Process incoming messages
@@ -84,12 +160,94 @@ async fn player_connected(ws: WebSocket, app: Arc<RwLock<App>>) {
             }
         };
 
-        process_message(msg, &app, &player, &player_id)
-            .await
-            .unwrap_or_else(|e| eprintln!("process message error(id={}): {:?}", &player_id, e));
+        // Stop serving a session resumed by another connection
+        if player.read().await.get_connection() != connection {
+            println!("Player {} resumed by another connection", &player_id);
+            break;
+        }
+
+        let (request_type, request_id) = c2s::Request::peek_header(msg.as_bytes());
+
+        if let Err(e) = process_message(msg, &app, &player, &player_id).await {
+            eprintln!("process message error(id={}): {:?}", &player_id, e);
+
+            player
+                .read()
+                .await
+                .send(&s2c::Message::Error {
+                    code: e.code(),
+                    request_type,
+                    request_id,
+                })
+                .await
+                .unwrap_or_else(|e| eprintln!("error reply error(id={}): {:?}", &player_id, e));
+        }
+    }
+
+    player_disconnected(&player_id, connection, &app).await;
+}
+
+/// Waits for the client's `Hello`, and negotiates the protocol version and capabilities of the
+/// connection.
+async fn handshake(ws_rx: &mut SplitStream<WebSocket>) -> Result<Handshake, HandshakeError> {
+    let msg = match timeout(HANDSHAKE_TIMEOUT, ws_rx.next()).await {
+        Ok(Some(Ok(msg))) => msg,
+        Ok(Some(Err(e))) => return Err(HandshakeError::Websocket(e)),
+        Ok(None) => return Err(HandshakeError::Closed),
+        Err(_) => return Err(HandshakeError::Timeout),
+    };
+
+    match c2s::Message::decode(msg.as_bytes())? {
+        c2s::Message::Hello {
+            protocol_version,
+            capabilities,
+            resume_token,
+        } => match version::negotiate(protocol_version, capabilities) {
+            Some((protocol_version, capabilities)) => Ok(Handshake {
+                protocol_version,
+                capabilities,
+                resume_token: resume_token.copied(),
+            }),
+            None => Err(HandshakeError::UnsupportedVersion {
+                version: protocol_version,
+            }),
+        },
+        _ => Err(HandshakeError::ExpectedHello),
+    }
+}
+
+async fn connect_player(
+    app: &Arc<RwLock<App>>,
+    tx: mpsc::UnboundedSender<Vec<u8>>,
+    remote: Option<SocketAddr>,
+    handshake: &Handshake,
+) -> Result<Arc<RwLock<Player>>, SendError> {
+    let remote_ip = remote.map(|addr| addr.ip());
+    let mut app = app.write().await;
+
+    if let Some(resume_token) = &handshake.resume_token {
+        let resumed = app
+            .resume_player(
+                resume_token,
+                tx.clone(),
+                remote_ip,
+                handshake.protocol_version,
+                handshake.capabilities,
+            )
+            .await?;
+
+        if let Some(player) = resumed {
+            return Ok(player);
+        }
     }
 
-    player_disconnected(&player_id, &app).await;
+    app.add_player(Player::new(
+        tx,
+        remote_ip,
+        handshake.protocol_version,
+        handshake.capabilities,
+    ))
+    .await
 }
 
 async fn process_message(
@@ -106,9 +264,30 @@ async fn process_message(
     app::process_message(player, app, msg.as_bytes()).await
 }
 
-async fn player_disconnected(session_id: &Uuid, app: &Arc<RwLock<App>>) {
-    if let Err(e) = app.write().await.remove_player(session_id).await {
-        eprintln!("failed to remove player: {:?}", e);
+async fn player_disconnected(session_id: &Uuid, connection: u32, app: &Arc<RwLock<App>>) {
+    let grace_period = app.read().await.get_config().resume_grace_period;
+
+    match app
+        .write()
+        .await
+        .disconnect_player(session_id, connection)
+        .await
+    {
+        // Remove the player if it doesn't resume its session in time
+        Ok(true) => {
+            let app = app.clone();
+            let session_id = *session_id;
+
+            tokio::task::spawn(async move {
+                sleep(grace_period).await;
+
+                if let Err(e) = app.write().await.expire_player(&session_id).await {
+                    eprintln!("failed to remove player: {:?}", e);
+                }
+            });
+        }
+        Ok(false) => {}
+        Err(e) => eprintln!("failed to remove player: {:?}", e),
     }
 }
 